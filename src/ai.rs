@@ -1,74 +1,557 @@
-use crate::chess::Chess;
-use std::f32::INFINITY;
+use crate::chess::{Chess, Piece};
+use crate::moves::Move;
+use crate::ordering::{mvv_lva, MoveOrdering};
+use crate::tt::{score_from_tt, score_to_tt, Bound, TranspositionTable};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
 pub struct AI {
-    depth: i32, //ms
-    is_white: bool,
+    depth: i32, //plies, the deepest iteration think will start
+    pub nodes: u64,
+    stop: Arc<AtomicBool>,
+    start: Instant,
+    hard_limit: Option<Duration>,
+    timed_out: bool,
+    pub tt: TranspositionTable,
+    ordering: MoveOrdering,
+    //triangular: row ply holds the best line found from ply on, pv_length[ply] is where it ends
+    pv: Box<[[Move; PV_SIZE]; PV_SIZE]>,
+    pv_length: [usize; PV_SIZE],
+    seldepth: i32,
+}
+
+//scores are centipawns for the side to move. a mate n plies from the root scores MATE - n,
+//so nearer mates score higher, and being mated in n scores -(MATE - n)
+pub const MATE: i32 = 32_000;
+//above every score, for the initial window
+pub const INFINITY: i32 = MATE + 1;
+//deeper than any search gets, scores within this of MATE are mates
+pub const MAX_PLY: i32 = 128;
+pub const DEFAULT_HASH_MB: usize = 16;
+const PV_SIZE: usize = MAX_PLY as usize + 1;
+
+//moves until mate if the score is one, positive when the side to move mates and negative when it is mated,
+//as UCI score mate counts them
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score >= MATE - MAX_PLY {
+        Some((MATE - score + 1) / 2)
+    } else if score <= -MATE + MAX_PLY {
+        Some(-(MATE + score) / 2)
+    } else {
+        None
+    }
+}
+//what a capture might gain beyond the captured piece, for delta pruning in the quiescence search
+const DELTA_MARGIN: i32 = 200;
+
+//how long one move may take: no new iteration starts after soft, and the search gives up at hard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeBudget {
+    pub soft: Duration,
+    pub hard: Duration,
+}
+
+impl TimeBudget {
+    //exactly this long, like UCI movetime or xboard st
+    pub fn fixed(time: Duration) -> Self {
+        TimeBudget {
+            soft: time,
+            hard: time,
+        }
+    }
+    //a share of the remaining clock plus most of the increment, expecting 30 more moves if moves_to_go isn't known
+    pub fn from_clock(remaining: Duration, increment: Duration, moves_to_go: Option<u32>) -> Self {
+        //leave a margin so the move arrives before the flag falls
+        let margin = Duration::from_millis(50).min(remaining / 2);
+        let usable = remaining - margin;
+        let share = remaining / moves_to_go.unwrap_or(30).max(1) + increment * 3 / 4;
+        let soft = share.min(usable).max(Duration::from_millis(1));
+        TimeBudget {
+            soft,
+            hard: (soft * 4).min(usable / 2).max(soft),
+        }
+    }
+}
+
+//what think found in the deepest iteration it finished
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Move,
+    pub score: i32,
+    pub pv: Vec<Move>, //the line the engine expects, starting with best_move
+    pub depth: i32,
+    pub seldepth: i32, //the deepest ply reached, quiescence search included
+    pub nodes: u64,
+    pub elapsed: Duration,
+    pub hashfull: u32, //per mille of the transposition table in use
 }
 
 impl AI {
-    pub fn new(depth: i32, is_white: bool) -> Self {
-        AI { depth, is_white }
+    pub fn new(depth: i32) -> Self {
+        AI {
+            depth,
+            nodes: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            start: Instant::now(),
+            hard_limit: None,
+            timed_out: false,
+            tt: TranspositionTable::new(DEFAULT_HASH_MB),
+            ordering: MoveOrdering::new(),
+            pv: Box::new([[Move::NONE; PV_SIZE]; PV_SIZE]),
+            pv_length: [0; PV_SIZE],
+            seldepth: 0,
+        }
+    }
+    pub fn set_depth(&mut self, depth: i32) {
+        self.depth = depth;
     }
-    pub fn best_move(&mut self, chess: &mut Chess) -> (u8, u8) {
+    //drops everything stored so far
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.tt = TranspositionTable::new(megabytes);
+    }
+    //setting the flag from another thread makes a running search give up as soon as possible
+    pub fn set_stop_flag(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = stop;
+    }
+    pub fn is_stopped(&self) -> bool {
+        self.timed_out || self.stop.load(Ordering::Relaxed)
+    }
+    //iterative deepening until the depth limit, the budget or the stop flag ends it.
+    //report sees every finished iteration, the last one is returned with the nodes and time of the whole search
+    pub fn think(
+        &mut self,
+        chess: &mut Chess,
+        budget: Option<TimeBudget>,
+        mut report: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        self.start = Instant::now();
+        self.hard_limit = budget.map(|budget| budget.hard);
+        self.timed_out = false;
+        self.nodes = 0;
+        self.tt.new_search();
+        self.ordering.new_search();
+        //something legal to play even if the first iteration doesn't finish
+        let mut result = SearchResult {
+            best_move: chess.get_all_moves().first().copied().unwrap_or(Move::NONE),
+            ..SearchResult::default()
+        };
+        for depth in 1..=self.depth.clamp(1, MAX_PLY - 1) {
+            self.seldepth = 0;
+            let Some((best_move, score)) = self.search_root(chess, depth) else {
+                break;
+            };
+            if best_move == Move::NONE {
+                break; //no legal moves
+            }
+            result = SearchResult {
+                best_move,
+                score,
+                pv: self.principal_variation(chess, depth),
+                depth,
+                seldepth: self.seldepth,
+                nodes: self.nodes,
+                elapsed: self.start.elapsed(),
+                hashfull: self.tt.hashfull(),
+            };
+            report(&result);
+            //a mate within the full-width depth is proven and won't change with more. one found
+            //further out by the quiescence search may yet have a shorter one, or be refuted
+            let out_of_time = budget.is_some_and(|budget| self.start.elapsed() >= budget.soft);
+            if out_of_time || MATE - score.abs() <= depth {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result.elapsed = self.start.elapsed();
+        result
+    }
+    //the line of the last search_root, continued with hash moves where a table hit cut it short
+    fn principal_variation(&mut self, chess: &mut Chess, depth: i32) -> Vec<Move> {
+        let mut pv = self.pv[0][..self.pv_length[0]].to_vec();
+        let mut played: Vec<_> = pv
+            .iter()
+            .map(|&legal_move| chess.make_move(legal_move))
+            .collect();
+        //no longer than the depth searched, hash moves can lead round in circles
+        while pv.len() < depth as usize {
+            let Some(entry) = self.tt.probe(chess.hash) else {
+                break;
+            };
+            if !chess.get_all_moves().contains(&entry.best_move) {
+                break;
+            }
+            pv.push(entry.best_move);
+            played.push(chess.make_move(entry.best_move));
+        }
+        while let Some(chess_move) = played.pop() {
+            chess.undo_move(chess_move);
+        }
+        pv
+    }
+    //the best line from ply on is legal_move followed by the best line found one ply deeper
+    fn update_pv(&mut self, ply: i32, legal_move: Move) {
+        let ply = ply as usize;
+        let end = self.pv_length[ply + 1].max(ply + 1);
+        let (line, deeper) = self.pv.split_at_mut(ply + 1);
+        line[ply][ply] = legal_move;
+        line[ply][ply + 1..end].copy_from_slice(&deeper[0][ply + 1..end]);
+        self.pv_length[ply] = end;
+    }
+    //searches straight to the full depth, None if there is no legal move. if the search is stopped
+    //first, the first legal move is all there is to play
+    pub fn best_move(&mut self, chess: &mut Chess) -> Option<Move> {
+        match self.search_root(chess, self.depth) {
+            Some((best_move, _)) if best_move != Move::NONE => Some(best_move),
+            _ => chess.get_all_moves().first().copied(),
+        }
+    }
+    //best move and its score for the side to move, None if the search was stopped before finishing
+    pub fn search_root(&mut self, chess: &mut Chess, depth: i32) -> Option<(Move, i32)> {
         let mut max = -INFINITY;
-        let mut best_move = (64, 64);
-        for (from, to) in chess.get_all_moves() {
-            let chess_move = chess.move_piece(from, to);
-            let eval = -self.search(self.depth - 1, -INFINITY, INFINITY, chess);
+        let mut best_move = Move::NONE;
+        self.pv_length[0] = 0;
+        let mut moves = chess.get_all_moves();
+        //the best move of the previous iteration first, it usually still is
+        let hash_move = self
+            .tt
+            .probe(chess.hash)
+            .map_or(Move::NONE, |entry| entry.best_move);
+        self.ordering.sort(chess, &mut moves, hash_move, 0);
+        for legal_move in moves {
+            let chess_move = chess.make_move(legal_move);
+            //only a better score than the best so far matters
+            let eval = -self.search(depth - 1, 1, -INFINITY, -max, chess);
             chess.undo_move(chess_move);
+            if self.is_stopped() {
+                return None;
+            }
             if eval > max {
                 max = eval;
-                best_move = (from, to);
+                best_move = legal_move;
+                self.update_pv(0, legal_move);
             }
         }
-        best_move
+        if best_move != Move::NONE {
+            self.tt
+                .store(chess.hash, depth, Bound::Exact, max, best_move);
+        }
+        Some((best_move, max))
     }
-    pub fn search(&self, depth: i32, mut alpha: f32, beta: f32, chess: &mut Chess) -> f32 {
+    //ply counts from the root, for mate distances
+    pub fn search(
+        &mut self,
+        depth: i32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        chess: &mut Chess,
+    ) -> i32 {
+        //no line found from here yet
+        self.pv_length[ply as usize] = ply as usize;
+        //the horizon, play out the captures before trusting the eval
         if depth == 0 {
-            return self.eval(chess);
+            return self.quiesce(ply, 0, alpha, beta, chess);
+        }
+        if self.visit(ply) {
+            return 0;
+        }
+        //a repeated position can be repeated again, so treat it as a draw. the fifty move rule
+        //only draws with a legal move left, a mate on the hundredth halfmove still counts
+        if chess.repetitions() > 0
+            || (chess.can_claim_fifty_move() && !chess.get_all_moves().is_empty())
+        {
+            return 0;
+        }
+        let mut hash_move = Move::NONE;
+        if let Some(entry) = self.tt.probe(chess.hash) {
+            hash_move = entry.best_move;
+            //a result from at least as deep a search is as good as searching again
+            if entry.depth >= depth {
+                let score = score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return beta,
+                    Bound::Upper if score <= alpha => return alpha,
+                    _ => (),
+                }
+            }
         }
-        let moves = chess.get_all_moves();
+        let mut moves = chess.get_all_moves();
         if moves.is_empty() {
             if chess.is_check(chess.king_loc()) {
-                return -INFINITY;
+                return -(MATE - ply);
             }
-            return 0.0;
+            return 0;
         }
-        for (from, to) in moves {
-            let chess_move = chess.move_piece(from, to);
-            let eval = -self.search(depth - 1, -beta, -alpha, chess);
+        self.ordering.sort(chess, &mut moves, hash_move, ply);
+        let mut best_move = Move::NONE;
+        for legal_move in moves {
+            let chess_move = chess.make_move(legal_move);
+            let eval = -self.search(depth - 1, ply + 1, -beta, -alpha, chess);
             chess.undo_move(chess_move);
+            //a stopped search returns made up scores, keep them out of the table
+            if self.is_stopped() {
+                return 0;
+            }
             if eval >= beta {
+                self.ordering.cutoff(chess, legal_move, depth, ply);
+                let score = score_to_tt(beta, ply);
+                self.tt
+                    .store(chess.hash, depth, Bound::Lower, score, legal_move);
                 return beta;
             }
-            alpha = alpha.max(eval);
+            if eval > alpha {
+                alpha = eval;
+                best_move = legal_move;
+                self.update_pv(ply, legal_move);
+            }
         }
+        let bound = if best_move == Move::NONE {
+            Bound::Upper
+        } else {
+            Bound::Exact
+        };
+        self.tt
+            .store(chess.hash, depth, bound, score_to_tt(alpha, ply), best_move);
         alpha
     }
-    pub fn eval(&self, chess: &Chess) -> f32 {
-        let mut eval = 0.0;
-        for piece in &chess.board {
-            eval += piece.evaluate();
+    //counts the node, keeps track of the deepest ply and returns whether the search has to stop
+    fn visit(&mut self, ply: i32) -> bool {
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+        //reading the clock is slow, so only look every few thousand nodes
+        if self.nodes.is_multiple_of(2048) {
+            if let Some(hard_limit) = self.hard_limit {
+                self.timed_out |= self.start.elapsed() >= hard_limit;
+            }
         }
-        if !self.is_white {
+        self.is_stopped()
+    }
+    //searches captures and promotions until the position is quiet, plus the checks on the first
+    //ply past the horizon. the side to move may stand pat on the eval instead, unless in check
+    fn quiesce(
+        &mut self,
+        ply: i32,
+        qply: i32,
+        mut alpha: i32,
+        beta: i32,
+        chess: &mut Chess,
+    ) -> i32 {
+        self.pv_length[ply as usize] = ply as usize;
+        if self.visit(ply) {
+            return 0;
+        }
+        let in_check = chess.is_check(chess.king_loc());
+        //in check every evasion has to be looked at, a capture may not be the way out
+        let mut moves = if in_check || qply == 0 {
+            chess.get_all_moves()
+        } else {
+            chess.get_captures()
+        };
+        //mate and stalemate come before the draw rules, so a mate on the board is never missed
+        if moves.is_empty() && (in_check || qply == 0) {
+            return if in_check { -(MATE - ply) } else { 0 };
+        }
+        if chess.repetitions() > 0
+            || chess.can_claim_fifty_move()
+            || chess.is_insufficient_material()
+        {
+            return 0;
+        }
+        let stand_pat = self.eval(chess);
+        if ply >= MAX_PLY {
+            return stand_pat;
+        }
+        if !in_check {
+            if stand_pat >= beta {
+                return beta;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+        moves.sort_unstable_by_key(|&legal_move| -mvv_lva(chess, legal_move));
+        for legal_move in moves {
+            let tactical = legal_move.is_capture() || legal_move.promotion().is_some();
+            if !in_check && tactical && legal_move.promotion().is_none() {
+                //delta pruning: even winning the piece and then some can't reach alpha
+                let captured = if legal_move.is_en_passant() {
+                    Piece::Wpawn
+                } else {
+                    chess.board[legal_move.to() as usize]
+                };
+                if stand_pat + captured.evaluate().abs() + DELTA_MARGIN <= alpha {
+                    continue;
+                }
+            }
+            let chess_move = chess.make_move(legal_move);
+            //quiet moves only come in at the first ply, and only if they give check
+            if !in_check && !tactical && !chess.is_check(chess.king_loc()) {
+                chess.undo_move(chess_move);
+                continue;
+            }
+            let eval = -self.quiesce(ply + 1, qply + 1, -beta, -alpha, chess);
+            chess.undo_move(chess_move);
+            if self.is_stopped() {
+                return 0;
+            }
+            if eval >= beta {
+                return beta;
+            }
+            if eval > alpha {
+                alpha = eval;
+                self.update_pv(ply, legal_move);
+            }
+        }
+        alpha
+    }
+    //from the point of view of the side to move, as negamax expects
+    pub fn eval(&self, chess: &Chess) -> i32 {
+        let mut eval = 0;
+        for piece in Piece::ALL {
+            eval += piece.evaluate() * chess.bitboard(piece).popcount() as i32;
+        }
+        //fade the eval as the fifty move rule gets closer, so the search prefers making progress
+        eval = eval * (200 - chess.halfmove_clock.min(100) as i32) / 200;
+        if chess.is_white_turn {
             eval
         } else {
             -eval
         }
     }
-    pub fn count_moves(depth: i32, chess: &mut Chess) -> i32 {
-        if depth == 0 {
-            return 1;
-        }
-        let mut num = 0;
-        let moves = chess.get_all_moves();
-        for move1 in moves.into_iter() {
-            let chess_move = chess.move_piece(move1.0, move1.1);
-            num += AI::count_moves(depth - 1, chess);
-            chess.undo_move(chess_move);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{GameStatus, Side};
+
+    #[test]
+    fn budgets() {
+        let budget = TimeBudget::from_clock(Duration::from_secs(60), Duration::from_secs(1), None);
+        assert_eq!(budget.soft, Duration::from_millis(2750));
+        assert_eq!(budget.hard, Duration::from_millis(11000));
+        //the last move before the time control may use what is left, minus the margin
+        let budget = TimeBudget::from_clock(Duration::from_secs(2), Duration::ZERO, Some(1));
+        assert_eq!(budget.soft, Duration::from_millis(1950));
+        assert_eq!(budget.hard, budget.soft);
+        let budget = TimeBudget::from_clock(Duration::from_millis(20), Duration::ZERO, None);
+        assert!(budget.hard <= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn iterative_deepening() {
+        //Qg7 and Qf8 both mate, seen at depth 1 since the quiescence search looks at evasions
+        let mut chess = Chess::from_fen("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let mut depths = vec![];
+        let result = AI::new(5).think(&mut chess, None, |iteration| depths.push(iteration.depth));
+        assert_eq!(depths, [1]);
+        chess.make_move(result.best_move);
+        assert_eq!(
+            chess.is_ending(),
+            GameStatus::Checkmate {
+                winner: Side::White
+            }
+        );
+
+        //a tiny budget still answers with a legal move from a finished iteration
+        let mut chess = Chess::new();
+        let mut ai = AI::new(64);
+        let budget = TimeBudget::fixed(Duration::from_millis(50));
+        let result = ai.think(&mut chess, Some(budget), |_| ());
+        assert!(chess.get_all_moves().contains(&result.best_move));
+        assert!(ai.start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn mate_distance() {
+        assert_eq!(mate_in(MATE - 1), Some(1));
+        assert_eq!(mate_in(MATE - 3), Some(2));
+        assert_eq!(mate_in(-(MATE - 2)), Some(-1));
+        assert_eq!(mate_in(900), None);
+        //Qg7 mates at once, one ply from the root
+        let mut chess = Chess::from_fen("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let mut scores = vec![];
+        AI::new(5).think(&mut chess, None, |iteration| scores.push(iteration.score));
+        assert_eq!(scores, [MATE - 1]);
+        //every move runs into Rh8 mate, the only one is still played
+        let mut chess = Chess::from_fen("k7/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+        let mut ai = AI::new(3);
+        assert_eq!(ai.best_move(&mut chess), Some(Move::new(0, 1, Move::QUIET)));
+        let mut scores = vec![];
+        ai.think(&mut chess, None, |iteration| scores.push(iteration.score));
+        assert_eq!(scores.last().copied().and_then(mate_in), Some(-1));
+        //already mated, nothing to play
+        let mut chess = Chess::from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(ai.best_move(&mut chess), None);
+    }
+
+    #[test]
+    fn principal_variation() {
+        //Re8+ Rxe8 Rxe8 mate
+        let mut chess = Chess::from_fen("r5k1/5ppp/8/8/8/8/4RPPP/4R1K1 w - - 0 1").unwrap();
+        let start = chess.clone();
+        let mut reported = vec![];
+        let result = AI::new(6).think(&mut chess, None, |iteration| {
+            reported.push(iteration.clone());
+        });
+        assert_eq!(chess, start);
+        //the deepest iteration, with the nodes and time of the whole search
+        assert_eq!(reported.last().map(|last| &last.pv), Some(&result.pv));
+        //the quiescence search sees it at depth 1, the search goes on until depth 3 proves it
+        assert_eq!(reported[0].score, MATE - 3);
+        assert_eq!((reported[0].depth, reported[0].seldepth), (1, 3));
+        assert_eq!(result.depth, 3);
+        assert_eq!(result.score, MATE - 3);
+        assert_eq!(result.pv.len(), 3);
+        assert_eq!(result.pv[0], result.best_move);
+        //the line is playable and the defence is forced
+        for &pv_move in &result.pv {
+            assert!(chess.get_all_moves().contains(&pv_move));
+            chess.make_move(pv_move);
         }
-        num
+        assert_eq!(
+            chess.is_ending(),
+            GameStatus::Checkmate {
+                winner: Side::White
+            }
+        );
+    }
+
+    #[test]
+    fn quiescence() {
+        //Qxd5 wins a pawn at depth 1, until exd5 is played out past the horizon
+        let mut chess = Chess::from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mut ai = AI::new(1);
+        let result = ai.think(&mut chess, None, |_| ());
+        assert_ne!(result.best_move.to(), 27);
+        //a hanging queen is taken even when the capture is the only ply left
+        let mut chess = Chess::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        assert!(ai.quiesce(0, 0, -MATE, MATE, &mut chess) > 0);
+        //standing pat isn't allowed in check, and without an evasion it is mate
+        let mut chess = Chess::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(ai.quiesce(3, 0, -MATE, MATE, &mut chess), -(MATE - 3));
+        //Nf7 mates with only knights left, the draw rules don't hide it
+        let mut chess = Chess::from_fen("6nk/8/6K1/4N3/8/8/8/8 w - - 0 1").unwrap();
+        let result = ai.think(&mut chess, None, |_| ());
+        assert_eq!(result.best_move, Move::new(28, 13, Move::QUIET));
+        assert_eq!(result.score, MATE - 1);
+    }
+
+    #[test]
+    fn mate_beats_fifty_move_rule() {
+        //Ra8 is the hundredth halfmove without a capture or pawn move, and mate
+        let mut chess = Chess::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80").unwrap();
+        let mut ai = AI::new(1);
+        assert_eq!(ai.think(&mut chess, None, |_| ()).score, MATE - 1);
+        chess.move_piece(56, 0, None);
+        assert_eq!(
+            ai.search(1, 1, -INFINITY, INFINITY, &mut chess),
+            -(MATE - 1)
+        );
+        assert_eq!(
+            ai.quiesce(1, 0, -INFINITY, INFINITY, &mut chess),
+            -(MATE - 1)
+        );
     }
 }
@@ -0,0 +1,39 @@
+use chess_ai::fen::START_FEN;
+use chess_ai::{Chess, AI};
+use std::time::Instant;
+
+//openings, middlegames and endgames, so changes to the search can be compared by node count
+const POSITIONS: [&str; 8] = [
+    START_FEN,
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8",
+    "2rq1rk1/pb1nbppp/1p2pn2/2pp4/2PP4/1PN1PN2/PBQ1BPPP/R4RK1 w - - 0 11",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5p1p/6p1/8/3R4/6P1/5PKP/3r4 b - - 0 1",
+];
+
+// bench [depth] searches every position to a fixed depth and prints the nodes each took
+fn main() {
+    let depth = std::env::args()
+        .nth(1)
+        .and_then(|depth| depth.parse().ok())
+        .unwrap_or(6);
+    let start = Instant::now();
+    let mut total = 0;
+    for fen in POSITIONS {
+        let mut chess = Chess::from_fen(fen).unwrap();
+        let mut ai = AI::new(depth);
+        let result = ai.think(&mut chess, None, |_| ());
+        println!("{:>10} {} {}", result.nodes, result.best_move, fen);
+        total += result.nodes;
+    }
+    let elapsed = start.elapsed().as_millis().max(1);
+    println!(
+        "{} nodes in {} ms, {} nps",
+        total,
+        elapsed,
+        total as u128 * 1000 / elapsed
+    );
+}
@@ -0,0 +1,16 @@
+use chess_ai::fen::START_FEN;
+use chess_ai::{perft, Chess};
+
+// perft <depth> [fen] prints the node count below every root move
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(depth) = args.get(1).and_then(|depth| depth.parse().ok()) else {
+        println!("usage: perft <depth> [fen]");
+        return;
+    };
+    let fen = args.get(2).map(String::as_str).unwrap_or(START_FEN);
+    match Chess::from_fen(fen) {
+        Ok(mut chess) => perft::print_divide(&mut chess, depth),
+        Err(error) => println!("invalid FEN: {}", error),
+    }
+}
@@ -0,0 +1,257 @@
+use chess_ai::ai::{mate_in, DEFAULT_HASH_MB};
+use chess_ai::{magic, Chess, TimeBudget, AI};
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// the engine speaks UCI on stdin/stdout, so it can be loaded into any UCI GUI
+fn main() {
+    //build the attack tables before the GUI starts the clock on the first move
+    magic::init();
+    let mut engine = Engine::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name chess_ai");
+                println!("id author chess_ai contributors");
+                println!(
+                    "option name Hash type spin default {} min 1 max 1024",
+                    DEFAULT_HASH_MB
+                );
+                println!("uciok");
+            }
+            Some(&"isready") => println!("readyok"),
+            Some(&"ucinewgame") => {
+                engine.wait();
+                engine.chess = Chess::new();
+                engine.ai().tt.clear();
+            }
+            Some(&"setoption") => {
+                engine.wait();
+                engine.set_option(&tokens[1..]);
+            }
+            Some(&"position") => {
+                engine.wait();
+                engine.position(&tokens[1..]);
+            }
+            Some(&"go") => engine.go(&tokens[1..]),
+            Some(&"stop") => engine.stop(),
+            Some(&"quit") => {
+                engine.stop();
+                break;
+            }
+            _ => (),
+        }
+    }
+    engine.wait();
+}
+
+struct Engine {
+    chess: Chess,
+    //kept between searches for its transposition table, the search thread has it while thinking
+    ai: Option<AI>,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<AI>>,
+    infinite: bool,
+}
+
+//how long a go without any depth or clock limit thinks, so it can't run for hours
+const DEFAULT_MOVETIME: u64 = 5000;
+
+#[derive(Default)]
+struct Limits {
+    depth: Option<i32>,
+    movetime: Option<u64>, //all times in milliseconds
+    time: Option<u64>,
+    inc: u64,
+    movestogo: Option<u64>,
+    infinite: bool,
+}
+
+impl Engine {
+    fn new() -> Self {
+        Engine {
+            chess: Chess::new(),
+            ai: Some(AI::new(64)),
+            stop: Arc::new(AtomicBool::new(false)),
+            search: None,
+            infinite: false,
+        }
+    }
+    // position [startpos | fen <fen>] [moves <move>...]
+    fn position(&mut self, tokens: &[&str]) {
+        let moves_at = tokens
+            .iter()
+            .position(|&token| token == "moves")
+            .unwrap_or(tokens.len());
+        let chess = match tokens.first() {
+            Some(&"startpos") => Ok(Chess::new()),
+            Some(&"fen") => Chess::from_fen(&tokens[1..moves_at].join(" ")),
+            _ => return,
+        };
+        self.chess = match chess {
+            Ok(chess) => chess,
+            Err(error) => {
+                println!("info string invalid FEN: {}", error);
+                return;
+            }
+        };
+        for text in tokens.iter().skip(moves_at + 1) {
+            match self.chess.parse_uci_move(text) {
+                Some(legal_move) => {
+                    self.chess.make_move(legal_move);
+                }
+                None => {
+                    println!("info string illegal move {}", text);
+                    return;
+                }
+            }
+        }
+    }
+    fn ai(&mut self) -> &mut AI {
+        self.ai.get_or_insert_with(|| AI::new(64))
+    }
+    // setoption name <name> [value <value>]
+    fn set_option(&mut self, tokens: &[&str]) {
+        let value_at = tokens
+            .iter()
+            .position(|&token| token == "value")
+            .unwrap_or(tokens.len());
+        let name = tokens.get(1..value_at).unwrap_or_default().join(" ");
+        let value = tokens.get(value_at + 1..).unwrap_or_default().join(" ");
+        if name.eq_ignore_ascii_case("hash") {
+            match value.parse::<usize>() {
+                Ok(megabytes) => self.ai().set_hash_size(megabytes.clamp(1, 1024)),
+                Err(_) => println!("info string invalid Hash value {}", value),
+            }
+        }
+    }
+    fn go(&mut self, tokens: &[&str]) {
+        self.wait();
+        let limits = Limits::parse(tokens, self.chess.is_white_turn);
+        self.stop = Arc::new(AtomicBool::new(false));
+        let budget = limits.budget();
+        let mut chess = self.chess.clone();
+        let max_depth = limits.depth.unwrap_or(64).max(1);
+        let mut ai = self.ai.take().unwrap_or_else(|| AI::new(64));
+        ai.set_depth(max_depth);
+        ai.set_stop_flag(self.stop.clone());
+        let infinite = limits.infinite;
+        self.infinite = infinite;
+        self.search = Some(thread::spawn(move || {
+            let result = ai.think(&mut chess, budget, |iteration| {
+                let elapsed = iteration.elapsed.as_millis().max(1) as u64;
+                let score = match mate_in(iteration.score) {
+                    Some(moves) => format!("mate {}", moves),
+                    None => format!("cp {}", iteration.score),
+                };
+                let pv: Vec<String> = iteration
+                    .pv
+                    .iter()
+                    .map(|pv_move| pv_move.to_string())
+                    .collect();
+                println!(
+                    "info depth {} seldepth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+                    iteration.depth,
+                    iteration.seldepth,
+                    score,
+                    iteration.nodes,
+                    iteration.nodes * 1000 / elapsed,
+                    iteration.hashfull,
+                    elapsed,
+                    pv.join(" ")
+                );
+            });
+            //go infinite must not answer before the GUI sends stop
+            while infinite && !ai.is_stopped() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            //Move::NONE prints as a8a8, UCI wants 0000 when there is nothing to play
+            if chess.get_all_moves().is_empty() {
+                println!("bestmove 0000");
+            } else if let [best_move, ponder, ..] = result.pv[..] {
+                println!("bestmove {} ponder {}", best_move, ponder);
+            } else {
+                println!("bestmove {}", result.best_move);
+            }
+            ai
+        }));
+    }
+    //lets a running search finish, unless it would never finish on its own
+    fn wait(&mut self) {
+        if self.infinite {
+            self.stop();
+        }
+        if let Some(search) = self.search.take() {
+            if let Ok(ai) = search.join() {
+                self.ai = Some(ai);
+            }
+        }
+    }
+    fn stop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(search) = self.search.take() {
+            if let Ok(ai) = search.join() {
+                self.ai = Some(ai);
+            }
+        }
+    }
+}
+
+impl Limits {
+    fn parse(tokens: &[&str], is_white_turn: bool) -> Self {
+        let mut limits = Limits::default();
+        let mut tokens = tokens.iter();
+        while let Some(&token) = tokens.next() {
+            //a GUI can send a negative clock once it has run out, which leaves no time at all
+            let mut value = || {
+                tokens
+                    .next()
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .map(|value| value.max(0) as u64)
+            };
+            match token {
+                "depth" => limits.depth = value().map(|depth| depth.min(64) as i32),
+                "movetime" => limits.movetime = value(),
+                "wtime" if is_white_turn => limits.time = value(),
+                "btime" if !is_white_turn => limits.time = value(),
+                "winc" if is_white_turn => limits.inc = value().unwrap_or(0),
+                "binc" if !is_white_turn => limits.inc = value().unwrap_or(0),
+                "movestogo" => limits.movestogo = value(),
+                "infinite" => limits.infinite = true,
+                //the other side's clock
+                "wtime" | "btime" | "winc" | "binc" => {
+                    value();
+                }
+                _ => (),
+            }
+        }
+        limits
+    }
+    //how long to think, None to search until told to stop or the depth is reached
+    fn budget(&self) -> Option<TimeBudget> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(movetime) = self.movetime {
+            return Some(TimeBudget::fixed(Duration::from_millis(movetime)));
+        }
+        let Some(time) = self.time else {
+            return match self.depth {
+                Some(_) => None,
+                None => Some(TimeBudget::fixed(Duration::from_millis(DEFAULT_MOVETIME))),
+            };
+        };
+        Some(TimeBudget::from_clock(
+            Duration::from_millis(time),
+            Duration::from_millis(self.inc),
+            self.movestogo.map(|moves| moves as u32),
+        ))
+    }
+}
@@ -0,0 +1,212 @@
+use chess_ai::ai::mate_in;
+use chess_ai::{magic, Chess, ChessMove, GameStatus, Side, TimeBudget, AI};
+use std::io::{self, BufRead};
+use std::time::Duration;
+
+// the engine speaks CECP (xboard/winboard protocol version 2) on stdin/stdout
+fn main() {
+    //the sliding attack tables, so building them never eats into a clock
+    magic::init();
+    let mut engine = Engine::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let (command, args) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match command {
+            "protover" => println!(
+                "feature myname=\"chess_ai\" usermove=1 setboard=1 ping=1 sigint=0 sigterm=0 colors=0 analyze=0 memory=1 done=1"
+            ),
+            "new" => engine.new_game(),
+            "force" => engine.force = true,
+            "go" => {
+                engine.force = false;
+                engine.engine_side = engine.chess.side_to_move();
+                engine.think();
+            }
+            "usermove" => engine.user_move(args),
+            "level" => engine.level(args),
+            "st" => engine.move_time = args.parse::<f64>().ok().map(|s| (s * 1000.0) as u64),
+            "sd" => engine.depth = args.parse().ok(),
+            "time" => engine.clock = args.parse::<u64>().ok().map(|cs| cs * 10),
+            "undo" => engine.undo(1),
+            "remove" => engine.undo(2),
+            "result" => engine.force = true,
+            "setboard" => engine.set_board(args),
+            "post" => engine.post = true,
+            "nopost" => engine.post = false,
+            "memory" => engine.memory(args),
+            "ping" => println!("pong {}", args),
+            "quit" => break,
+            //xboard, accepted, rejected, otim, hard, easy, random, computer and the rest need no answer
+            _ => (),
+        }
+    }
+}
+
+struct Engine {
+    chess: Chess,
+    history: Vec<ChessMove>,
+    force: bool,
+    engine_side: Side,
+    depth: Option<i32>,
+    move_time: Option<u64>, //all times in milliseconds
+    moves_per_session: u64,
+    inc: u64,
+    clock: Option<u64>,
+    post: bool,
+    ai: AI, //kept between moves for its transposition table
+}
+
+impl Engine {
+    fn new() -> Self {
+        Engine {
+            chess: Chess::new(),
+            history: vec![],
+            force: false,
+            engine_side: Side::Black,
+            depth: None,
+            move_time: None,
+            moves_per_session: 0,
+            inc: 0,
+            clock: None,
+            post: false,
+            ai: AI::new(64),
+        }
+    }
+    fn new_game(&mut self) {
+        self.chess = Chess::new();
+        self.history.clear();
+        self.force = false;
+        self.engine_side = Side::Black;
+        self.depth = None;
+        self.clock = None;
+        self.ai.tt.clear();
+    }
+    // memory <megabytes>, for the hash table since that is all the engine allocates
+    fn memory(&mut self, args: &str) {
+        if let Ok(megabytes) = args.trim().parse::<usize>() {
+            self.ai.set_hash_size(megabytes.clamp(1, 1024));
+        }
+    }
+    fn set_board(&mut self, fen: &str) {
+        match Chess::from_fen(fen) {
+            Ok(chess) => {
+                self.chess = chess;
+                self.history.clear();
+            }
+            Err(error) => println!("tellusererror Illegal position: {}", error),
+        }
+    }
+    // level MPS BASE INC, where BASE is minutes or minutes:seconds and INC is seconds
+    fn level(&mut self, args: &str) {
+        let fields: Vec<&str> = args.split_whitespace().collect();
+        if fields.len() != 3 {
+            return;
+        }
+        self.moves_per_session = fields[0].parse().unwrap_or(0);
+        let base = match fields[1].split_once(':') {
+            Some((minutes, seconds)) => {
+                minutes.parse::<u64>().unwrap_or(0) * 60 + seconds.parse::<u64>().unwrap_or(0)
+            }
+            None => fields[1].parse::<u64>().unwrap_or(0) * 60,
+        };
+        self.clock = Some(base * 1000);
+        self.inc = (fields[2].parse::<f64>().unwrap_or(0.0) * 1000.0) as u64;
+        self.move_time = None;
+    }
+    fn user_move(&mut self, text: &str) {
+        let Some(legal_move) = self.chess.parse_uci_move(text) else {
+            println!("Illegal move: {}", text);
+            return;
+        };
+        self.history.push(self.chess.make_move(legal_move));
+        if self.report_result() {
+            return;
+        }
+        if !self.force && self.chess.side_to_move() == self.engine_side {
+            self.think();
+        }
+    }
+    fn undo(&mut self, count: usize) {
+        for _ in 0..count {
+            if let Some(chess_move) = self.history.pop() {
+                self.chess.undo_move(chess_move);
+            }
+        }
+    }
+    //prints the result and returns true if the last move ended the game
+    fn report_result(&mut self) -> bool {
+        let result = match self.chess.is_ending() {
+            GameStatus::Checkmate {
+                winner: Side::White,
+            } => "1-0 {White mates}",
+            GameStatus::Checkmate {
+                winner: Side::Black,
+            } => "0-1 {Black mates}",
+            GameStatus::Stalemate => "1/2-1/2 {Stalemate}",
+            GameStatus::InsufficientMaterial => "1/2-1/2 {Insufficient material}",
+            GameStatus::Repetition => "1/2-1/2 {Draw by repetition}",
+            GameStatus::FiftyMove => "1/2-1/2 {Seventy-five move rule}",
+            _ => return false,
+        };
+        println!("{}", result);
+        true
+    }
+    //how long to think, None to search to the depth limit
+    fn budget(&self) -> Option<TimeBudget> {
+        if let Some(move_time) = self.move_time {
+            return Some(TimeBudget::fixed(Duration::from_millis(move_time)));
+        }
+        let Some(clock) = self.clock else {
+            //no time control was given, so only sd can end the search
+            return match self.depth {
+                Some(_) => None,
+                None => Some(TimeBudget::fixed(Duration::from_secs(5))),
+            };
+        };
+        let moves_left = (self.moves_per_session > 0).then(|| {
+            let played = (self.chess.fullmove_number as u64 - 1) % self.moves_per_session;
+            (self.moves_per_session - played) as u32
+        });
+        Some(TimeBudget::from_clock(
+            Duration::from_millis(clock),
+            Duration::from_millis(self.inc),
+            moves_left,
+        ))
+    }
+    fn think(&mut self) {
+        if self.chess.is_ending().is_over() {
+            return;
+        }
+        self.ai.set_depth(self.depth.unwrap_or(64).max(1));
+        let (budget, post) = (self.budget(), self.post);
+        let result = self.ai.think(&mut self.chess, budget, |iteration| {
+            if post {
+                //mates are 100000 + N for mate in N moves and -100000 - N for mated in N
+                let score = match mate_in(iteration.score) {
+                    Some(moves) if moves > 0 => 100000 + moves,
+                    Some(moves) => -100000 + moves,
+                    None => iteration.score,
+                };
+                let pv: Vec<String> = iteration
+                    .pv
+                    .iter()
+                    .map(|pv_move| pv_move.to_string())
+                    .collect();
+                // depth, score, time in centiseconds, nodes, principal variation
+                println!(
+                    "{} {} {} {} {}",
+                    iteration.depth,
+                    score,
+                    iteration.elapsed.as_millis() / 10,
+                    iteration.nodes,
+                    pv.join(" ")
+                );
+            }
+        });
+        println!("move {}", result.best_move);
+        self.history.push(self.chess.make_move(result.best_move));
+        self.report_result();
+    }
+}
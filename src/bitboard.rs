@@ -1,4 +1,7 @@
-#[derive(Debug, Clone, Copy)]
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not, Shl};
+
+//bit i is square i, from a8 (0) to h1 (63)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Bitboard(pub u64);
 
 impl Bitboard {
@@ -10,24 +13,24 @@ impl Bitboard {
         self.0 |= 1 << index;
     }
 
-    // pub fn clear_bit(&mut self, index: usize) {
-    //     self.0 &= !(1 << index);
-    // }
+    pub fn clear_bit(&mut self, index: u8) {
+        self.0 &= !(1 << index);
+    }
 
     pub fn get_bit(&self, index: u8) -> bool {
         (self.0 & (1 << index)) != 0
     }
 
-    // pub fn get_pieces(&self) -> Vec<usize> {
-    //     let mut pieces = Vec::new();
-    //     let mut bitboard = self.0;
-    //     while bitboard != 0 {
-    //         let index = bitboard.trailing_zeros() as usize;
-    //         pieces.push(index);
-    //         bitboard &= bitboard - 1;
-    //     }
-    //     pieces
-    // }
+    pub fn popcount(&self) -> u32 {
+        self.0.count_ones()
+    }
+    //the set squares from a8 to h1
+    pub fn squares(self) -> Squares {
+        Squares(self.0)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
     pub fn switch_on_indices(&mut self, indices: &[u8]) {
         for &index in indices {
             self.0 |= 1 << index;
@@ -63,28 +66,44 @@ impl Bitboard {
     //     bitboard
     // }
 
-    pub fn ray(from: u8, to: u8) -> Bitboard {
-        let from_row = from / 8;
-        let from_col = from % 8;
-        let to_row = to / 8;
-        let to_col = to % 8;
-
-        // Calculate the row and column differences
-        let row_diff = (to_row as i32 - from_row as i32).signum();
-        let col_diff = (to_col as i32 - from_col as i32).signum();
-
-        let mut current_square = from;
-        let mut ray_bitboard = Bitboard::empty();
-
-        // Generate the ray by moving along the row and column differences
-        while current_square != to {
-            current_square = ((current_square as i32) + row_diff * 8 + col_diff) as u8;
-            if !(0..63).contains(&current_square) {
-                break;
+    //the squares strictly between two squares on one rank, file or diagonal, empty if they share none
+    pub fn between(from: u8, to: u8) -> Bitboard {
+        let mut between = Bitboard::empty();
+        if let Some((row_step, col_step)) = Bitboard::direction(from, to) {
+            let (mut row, mut col) = ((from / 8) as i8 + row_step, (from % 8) as i8 + col_step);
+            while (row * 8 + col) as u8 != to {
+                between.set_bit((row * 8 + col) as u8);
+                row += row_step;
+                col += col_step;
             }
-            ray_bitboard.set_bit(current_square);
         }
-        ray_bitboard
+        between
+    }
+    //the whole rank, file or diagonal through both squares, empty if they share none
+    pub fn line(a: u8, b: u8) -> Bitboard {
+        let mut line = Bitboard::empty();
+        let Some((row_step, col_step)) = Bitboard::direction(a, b) else {
+            return line;
+        };
+        for (row_step, col_step) in [(row_step, col_step), (-row_step, -col_step)] {
+            let (mut row, mut col) = ((a / 8) as i8, (a % 8) as i8);
+            while (0..8).contains(&row) && (0..8).contains(&col) {
+                line.set_bit((row * 8 + col) as u8);
+                row += row_step;
+                col += col_step;
+            }
+        }
+        line
+    }
+    //the row and column step from a towards b, if they are on one line
+    fn direction(a: u8, b: u8) -> Option<(i8, i8)> {
+        let row_diff = (b / 8) as i8 - (a / 8) as i8;
+        let col_diff = (b % 8) as i8 - (a % 8) as i8;
+        let aligned = row_diff == 0 || col_diff == 0 || row_diff.abs() == col_diff.abs();
+        if a == b || !aligned {
+            return None;
+        }
+        Some((row_diff.signum(), col_diff.signum()))
     }
     // pub fn from_piece(piece: Piece, board: &[Piece; 64]) -> Bitboard {
     //     let mask = board
@@ -95,3 +114,119 @@ impl Bitboard {
     //     Bitboard(mask)
     // }
 }
+
+//yields the set squares of a bitboard from a8 to h1, clearing the lowest bit each step
+#[derive(Debug, Clone)]
+pub struct Squares(u64);
+
+impl Iterator for Squares {
+    type Item = u8;
+    fn next(&mut self) -> Option<u8> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as u8;
+        self.0 &= self.0 - 1;
+        Some(index)
+    }
+}
+
+impl IntoIterator for Bitboard {
+    type Item = u8;
+    type IntoIter = Squares;
+    fn into_iter(self) -> Squares {
+        self.squares()
+    }
+}
+
+impl BitAnd for Bitboard {
+    type Output = Bitboard;
+    fn bitand(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 & other.0)
+    }
+}
+
+impl BitOr for Bitboard {
+    type Output = Bitboard;
+    fn bitor(self, other: Bitboard) -> Bitboard {
+        Bitboard(self.0 | other.0)
+    }
+}
+
+impl BitAndAssign for Bitboard {
+    fn bitand_assign(&mut self, other: Bitboard) {
+        self.0 &= other.0;
+    }
+}
+
+impl BitOrAssign for Bitboard {
+    fn bitor_assign(&mut self, other: Bitboard) {
+        self.0 |= other.0;
+    }
+}
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
+    }
+}
+
+//towards higher squares, so shifting by 8 moves every bit one rank down the board
+impl Shl<u8> for Bitboard {
+    type Output = Bitboard;
+    fn shl(self, shift: u8) -> Bitboard {
+        Bitboard(self.0 << shift)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_bits() {
+        let mut bitboard = Bitboard::empty();
+        bitboard.set_bit(0);
+        bitboard.set_bit(36);
+        bitboard.set_bit(63);
+        assert_eq!(bitboard.popcount(), 3);
+        assert_eq!(bitboard.squares().collect::<Vec<u8>>(), [0, 36, 63]);
+        bitboard.clear_bit(36);
+        assert!(!bitboard.get_bit(36));
+        assert!(Bitboard::empty().is_empty());
+    }
+
+    #[test]
+    fn operators() {
+        let a = Bitboard(0b1100);
+        let b = Bitboard(0b1010);
+        assert_eq!(a & b, Bitboard(0b1000));
+        assert_eq!(a | b, Bitboard(0b1110));
+        assert_eq!(!Bitboard::empty(), Bitboard(u64::MAX));
+        assert_eq!(a << 8, Bitboard(0b1100 << 8));
+        let mut c = a;
+        c |= b;
+        c &= !Bitboard(0b0010);
+        assert_eq!(c, Bitboard(0b1100));
+    }
+
+    #[test]
+    fn lines() {
+        //e1 to e8 along the file, b1 to h7 along the diagonal
+        assert_eq!(
+            Bitboard::between(60, 4).squares().collect::<Vec<u8>>(),
+            [12, 20, 28, 36, 44, 52]
+        );
+        assert_eq!(Bitboard::between(57, 15).popcount(), 5);
+        assert!(Bitboard::between(57, 62).get_bit(60));
+        //a knight's jump shares no line, and neighbours have nothing in between
+        assert!(Bitboard::between(62, 45).is_empty());
+        assert!(Bitboard::between(62, 63).is_empty());
+        //h1 to a8 wraps nowhere: the long diagonal has exactly eight squares
+        assert_eq!(Bitboard::line(63, 54).popcount(), 8);
+        assert!(Bitboard::line(63, 54).get_bit(0));
+        assert_eq!(Bitboard::line(9, 10).popcount(), 8);
+        assert!(Bitboard::line(1, 18).is_empty());
+    }
+}
@@ -1,10 +1,17 @@
 use crate::bitboard::Bitboard;
-use macroquad::prelude::*;
+use crate::magic;
+use crate::moves::{Move, MoveList};
+use crate::zobrist::KEYS;
 
-#[derive(Debug, Clone)]
+//a8, c8 ... h1: the squares where row plus column is even
+const LIGHT_SQUARES: Bitboard = Bitboard(0xAA55_AA55_AA55_AA55);
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Chess {
     pub board: [Piece; 64],
-    pub moves: Vec<u8>,
+    pub pieces: [Bitboard; 12], //one board per piece, in the order of Piece::ALL
+    pub white_pieces: Bitboard,
+    pub black_pieces: Bitboard,
     pub castling: [bool; 4], //white, white long, black, black long
     pub en_passant: u8,
     pub is_white_turn: bool,
@@ -12,15 +19,23 @@ pub struct Chess {
     pub black_king: u8,
     pub white_attack: Bitboard,
     pub black_attack: Bitboard,
-    pub white_pins: Bitboard,
-    pub black_pins: Bitboard,
-    last_attack: Bitboard,
-    last_pin: Bitboard,
-    checking_pieces: Vec<u8>,
+    pub white_pins: Bitboard, //black pieces pinned to their king by white sliders
+    pub black_pins: Bitboard, //white pieces pinned by black sliders
+    checkers: Bitboard,       //pieces of the side that just moved giving check
     pub knight_moves: [[u8; 8]; 64],
     pawn_moves: [[u8; 4]; 128],
     king_moves: [[u8; 8]; 64],
-    pub last_move: (u8, u8),
+    pub last_move: Move,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    pub hash: u64,
+    history: Vec<u64>, //keys of the positions before each move
+}
+
+impl Default for Chess {
+    fn default() -> Self {
+        Chess::new()
+    }
 }
 
 impl Chess {
@@ -56,9 +71,11 @@ impl Chess {
             .for_each(|piece| *piece = Piece::Wpawn);
         let white_attack = Bitboard::empty();
         let black_attack = Bitboard::empty();
-        Chess {
+        let mut chess = Chess {
             board,
-            moves: Vec::new(),
+            pieces: [Bitboard::empty(); 12],
+            white_pieces: Bitboard::empty(),
+            black_pieces: Bitboard::empty(),
             castling: [true; 4],
             en_passant: 64,
             is_white_turn: true,
@@ -68,69 +85,131 @@ impl Chess {
             black_attack,
             white_pins: Bitboard::empty(),
             black_pins: Bitboard::empty(),
-            last_attack: Bitboard::empty(),
-            last_pin: Bitboard::empty(),
-            checking_pieces: Vec::new(),
+            checkers: Bitboard::empty(),
             knight_moves: Chess::precompute_knight(),
             pawn_moves: Chess::precompute_pawn(),
             king_moves: Chess::precompute_king(),
-            last_move: (64, 64),
+            last_move: Move::NONE,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            history: Vec::new(),
+        };
+        chess.refresh_bitboards();
+        chess.hash = chess.compute_hash();
+        chess
+    }
+    //rebuilds the piece and colour bitboards from the board array
+    pub fn refresh_bitboards(&mut self) {
+        self.pieces = [Bitboard::empty(); 12];
+        self.white_pieces = Bitboard::empty();
+        self.black_pieces = Bitboard::empty();
+        for (i, piece) in self.board.into_iter().enumerate() {
+            if piece != Piece::Empty {
+                self.pieces[piece.index()].set_bit(i as u8);
+                self.side_pieces_mut(piece.is_white()).set_bit(i as u8);
+            }
         }
     }
-    fn is_opponent_piece(&self, piece1: Piece, piece2: Piece) -> bool {
-        if piece1 == Piece::Empty || piece2 == Piece::Empty {
-            return false;
+    //puts piece on the square, replacing what stood there, and keeps the bitboards in step
+    fn set_square(&mut self, index: u8, piece: Piece) {
+        let old = self.board[index as usize];
+        if old != Piece::Empty {
+            self.pieces[old.index()].clear_bit(index);
+            self.side_pieces_mut(old.is_white()).clear_bit(index);
+        }
+        if piece != Piece::Empty {
+            self.pieces[piece.index()].set_bit(index);
+            self.side_pieces_mut(piece.is_white()).set_bit(index);
+        }
+        self.board[index as usize] = piece;
+    }
+    pub fn bitboard(&self, piece: Piece) -> Bitboard {
+        self.pieces[piece.index()]
+    }
+    pub fn side_pieces(&self, white: bool) -> Bitboard {
+        if white {
+            self.white_pieces
+        } else {
+            self.black_pieces
         }
-        piece1.is_white() != piece2.is_white()
     }
+    fn side_pieces_mut(&mut self, white: bool) -> &mut Bitboard {
+        if white {
+            &mut self.white_pieces
+        } else {
+            &mut self.black_pieces
+        }
+    }
+    pub fn occupied(&self) -> Bitboard {
+        self.white_pieces | self.black_pieces
+    }
+    //pseudo legal moves are legal unless they leave the own king attacked
     pub fn is_legal(&self, from: u8, to: u8) -> bool {
-        if self.is_check(self.king_loc()) {
-            if self.board[from as usize] == Piece::Bking
-                || self.board[from as usize] == Piece::Wking
-            {
-                //moving the king
-                !self.is_check(to)
-            } else if self.checking_pieces.len() == 1 {
-                //blocking is only possible when a single check happens.
-                //check if blockable
-                if matches!(
-                    self.board[self.checking_pieces[0] as usize],
-                    Piece::Bbishop
-                        | Piece::Wbishop
-                        | Piece::Brook
-                        | Piece::Wrook
-                        | Piece::Bqueen
-                        | Piece::Wqueen
-                ) {
-                    let blocking_ray = Bitboard::ray(self.king_loc(), self.checking_pieces[0]);
-                    let from_to_ray = Bitboard::ray(from, to);
-                    from_to_ray.0 & blocking_ray.0 != 0 && blocking_ray.get_bit(to)
-                } else {
-                    to == self.checking_pieces[0]
-                }
-            } else {
-                false // double check- stays false
-            }
+        let king = self.king_loc();
+        if from == king {
+            //the enemy attack map was built without this king, so it can't hide behind itself
+            return !self.is_check(to);
+        }
+        if self.checkers.popcount() > 1 {
+            return false; //double check, only the king can move
+        }
+        let pinned = if self.is_white_turn {
+            self.black_pins
         } else {
-            //king is safe, just dont break pins
-            if self.is_white_turn {
-                if self.board[from as usize] == Piece::Wking {
-                    //piece is king
-                    !self.is_check(to)
-                } else if self.black_pins.get_bit(from) {
-                    //piece is pinned
-                    self.black_pins.get_bit(to) //move doesnt break pin
-                } else {
-                    true // piece isnt pinned
-                }
-            } else if self.board[from as usize] == Piece::Bking {
-                !self.is_check(to)
-            } else if self.white_pins.get_bit(from) {
-                self.white_pins.get_bit(to)
-            } else {
-                true
-            }
+            self.white_pins
+        };
+        //a pinned piece may only move along the line through its king and the pinner
+        if pinned.get_bit(from) && !Bitboard::line(king, from).get_bit(to) {
+            return false;
+        }
+        if matches!(self.board[from as usize], Piece::Wpawn | Piece::Bpawn) && to == self.en_passant
+        {
+            return self.en_passant_is_safe(from, to);
+        }
+        match self.checkers.into_iter().next() {
+            //capture the checking piece or step in between
+            Some(checker) => to == checker || Bitboard::between(king, checker).get_bit(to),
+            None => true,
+        }
+    }
+    //en passant takes two pawns off one rank, so replay it on the occupancy and look for attacks on the king
+    fn en_passant_is_safe(&self, from: u8, to: u8) -> bool {
+        let king = self.king_loc();
+        let captured = if self.is_white_turn { to + 8 } else { to - 8 };
+        let mut occupied = self.occupied();
+        occupied.clear_bit(from);
+        occupied.clear_bit(captured);
+        occupied.set_bit(to);
+        let [pawns, knights, bishops, rooks, queens] = if self.is_white_turn {
+            [
+                Piece::Bpawn,
+                Piece::Bknight,
+                Piece::Bbishop,
+                Piece::Brook,
+                Piece::Bqueen,
+            ]
+        } else {
+            [
+                Piece::Wpawn,
+                Piece::Wknight,
+                Piece::Wbishop,
+                Piece::Wrook,
+                Piece::Wqueen,
+            ]
         }
+        .map(|piece| self.bitboard(piece));
+        let sliders = (magic::rook_attacks(king, occupied) & (rooks | queens))
+            | (magic::bishop_attacks(king, occupied) & (bishops | queens));
+        //a knight or pawn giving check stays, unless it is the pawn taken
+        let mut leapers = self.checkers & (pawns | knights);
+        leapers.clear_bit(captured);
+        sliders.is_empty() && leapers.is_empty()
+    }
+    pub fn has_adjacent(&self, index: u8, piece: Piece) -> bool {
+        let col = index % 8;
+        (col > 0 && self.board[index as usize - 1] == piece)
+            || (col < 7 && self.board[index as usize + 1] == piece)
     }
     pub fn king_loc(&self) -> u8 {
         if self.is_white_turn {
@@ -147,8 +226,8 @@ impl Chess {
         }
         .get_bit(king_position)
     }
-    pub fn move_piece(&mut self, from: u8, to: u8) -> ChessMove {
-        let mut rook_cancel: u8 = 4;
+    //promotion is the piece a pawn reaching the last rank turns into, a queen if none is given
+    pub fn move_piece(&mut self, from: u8, to: u8, promotion: Option<Piece>) -> ChessMove {
         let mut castle_flag = false;
         let mut en_passant_flag = false;
         let king_loc = if self.is_white_turn {
@@ -156,34 +235,47 @@ impl Chess {
         } else {
             self.black_king
         };
+        let castling = self.castling;
+        let en_passant = self.en_passant;
+        let halfmove_clock = self.halfmove_clock;
+        let fullmove_number = self.fullmove_number;
+        let hash = self.hash;
         let mut captured_piece = self.board[to as usize];
-        // self.last_attack = if self.is_white_turn {
-        //     self.white_attack
-        // } else {
-        //     self.black_attack
-        // };
         let piece = self.board[from as usize];
         if let Piece::Wpawn = piece {
             if to == self.en_passant {
                 en_passant_flag = true;
                 let captured_piece_index = to + 8;
                 captured_piece = Piece::Bpawn;
-                self.board[captured_piece_index as usize] = Piece::Empty;
+                self.set_square(captured_piece_index, Piece::Empty);
+                self.hash ^= KEYS.piece(Piece::Bpawn, captured_piece_index);
             }
         } else if let Piece::Bpawn = piece {
             if to == self.en_passant {
                 en_passant_flag = true;
                 let captured_piece_index = to - 8;
                 captured_piece = Piece::Wpawn;
-                self.board[captured_piece_index as usize] = Piece::Empty;
+                self.set_square(captured_piece_index, Piece::Empty);
+                self.hash ^= KEYS.piece(Piece::Wpawn, captured_piece_index);
             }
         }
+        if !en_passant_flag {
+            self.hash ^= KEYS.piece(captured_piece, to);
+        }
         self.en_passant = 64;
+        if matches!(piece, Piece::Wpawn | Piece::Bpawn) || captured_piece != Piece::Empty {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if !self.is_white_turn {
+            self.fullmove_number += 1;
+        }
         match piece {
             Piece::Bking => {
                 self.castling[2] = false;
                 self.castling[3] = false;
-                self.black_king = to; //maybe need to save last_king in undo_move
+                self.black_king = to;
             }
             Piece::Wking => {
                 self.castling[0] = false;
@@ -193,69 +285,116 @@ impl Chess {
             Piece::Brook => {
                 if from == 7 {
                     self.castling[2] = false;
-                    rook_cancel = 2;
                 } else if from == 0 {
                     self.castling[3] = false;
-                    rook_cancel = 3;
                 }
             }
             Piece::Wrook => {
                 if from == 63 {
                     self.castling[0] = false;
-                    rook_cancel = 0;
                 } else if from == 56 {
                     self.castling[1] = false;
-                    rook_cancel = 1;
                 }
             }
-            Piece::Wpawn => {
-                if from - to == 16 {
-                    self.en_passant = to + 8;
-                }
+            //only keep en passant squares that can be used, so they dont split repeated positions
+            Piece::Wpawn if from - to == 16 && self.has_adjacent(to, Piece::Bpawn) => {
+                self.en_passant = to + 8;
             }
-            Piece::Bpawn => {
-                if to - from == 16 {
-                    self.en_passant = to - 8;
-                }
+            Piece::Bpawn if to - from == 16 && self.has_adjacent(to, Piece::Wpawn) => {
+                self.en_passant = to - 8;
             }
             _ => (),
         }
+        //a rook captured on its corner takes the castling right with it
+        match to {
+            63 => self.castling[0] = false,
+            56 => self.castling[1] = false,
+            7 => self.castling[2] = false,
+            0 => self.castling[3] = false,
+            _ => (),
+        }
 
         // Update the board
-        self.board[from as usize] = Piece::Empty;
+        self.set_square(from, Piece::Empty);
 
         if piece == Piece::Wpawn && to < 8 {
-            self.board[to as usize] = Piece::Wqueen;
+            self.set_square(to, promotion.unwrap_or(Piece::Wqueen));
         } else if piece == Piece::Bpawn && to >= 56 {
-            self.board[to as usize] = Piece::Bqueen;
+            self.set_square(to, promotion.unwrap_or(Piece::Bqueen));
         } else {
-            self.board[to as usize] = piece;
+            self.set_square(to, piece);
         }
+        self.hash ^= KEYS.piece(piece, from) ^ KEYS.piece(self.board[to as usize], to);
         if let Piece::Bking = piece {
             if from == 4 && to == 6 {
-                // Perform kingside castling for white
-                self.board[7] = Piece::Empty;
-                self.board[5] = Piece::Brook;
+                // Perform kingside castling for black
+                self.set_square(7, Piece::Empty);
+                self.set_square(5, Piece::Brook);
+                self.hash ^= KEYS.piece(Piece::Brook, 7) ^ KEYS.piece(Piece::Brook, 5);
                 castle_flag = true;
             } else if from == 4 && to == 2 {
-                // Perform queenside castling for white
-                self.board[0] = Piece::Empty;
-                self.board[3] = Piece::Brook;
+                // Perform queenside castling for black
+                self.set_square(0, Piece::Empty);
+                self.set_square(3, Piece::Brook);
+                self.hash ^= KEYS.piece(Piece::Brook, 0) ^ KEYS.piece(Piece::Brook, 3);
                 castle_flag = true;
             }
         } else if let Piece::Wking = piece {
             if from == 60 && to == 62 {
-                // Perform kingside castling for black
-                self.board[63] = Piece::Empty;
-                self.board[61] = Piece::Wrook;
+                // Perform kingside castling for white
+                self.set_square(63, Piece::Empty);
+                self.set_square(61, Piece::Wrook);
+                self.hash ^= KEYS.piece(Piece::Wrook, 63) ^ KEYS.piece(Piece::Wrook, 61);
                 castle_flag = true;
             } else if from == 60 && to == 58 {
-                // Perform queenside castling for black
-                self.board[56] = Piece::Empty;
-                self.board[59] = Piece::Wrook;
+                // Perform queenside castling for white
+                self.set_square(56, Piece::Empty);
+                self.set_square(59, Piece::Wrook);
+                self.hash ^= KEYS.piece(Piece::Wrook, 56) ^ KEYS.piece(Piece::Wrook, 59);
                 castle_flag = true;
             }
         }
+        self.hash ^= KEYS.castling(castling) ^ KEYS.castling(self.castling);
+        self.hash ^= KEYS.en_passant(en_passant) ^ KEYS.en_passant(self.en_passant);
+        self.hash ^= KEYS.black_to_move();
+        self.history.push(hash);
+        let flags = if castle_flag {
+            if to % 8 == 6 {
+                Move::KING_CASTLE
+            } else {
+                Move::QUEEN_CASTLE
+            }
+        } else if en_passant_flag {
+            Move::EN_PASSANT
+        } else if self.board[to as usize] != piece {
+            Move::promotion_flags(self.board[to as usize], captured_piece != Piece::Empty)
+        } else if matches!(piece, Piece::Wpawn | Piece::Bpawn) && from.abs_diff(to) == 16 {
+            Move::DOUBLE_PUSH
+        } else if captured_piece != Piece::Empty {
+            Move::CAPTURE
+        } else {
+            Move::QUIET
+        };
+        let chess_move = ChessMove {
+            from,
+            to,
+            moved_piece: piece,
+            castle_flag,
+            en_passant_flag,
+            captured_piece,
+            last_king_placement: king_loc,
+            castling,
+            en_passant,
+            white_attack: self.white_attack,
+            black_attack: self.black_attack,
+            white_pins: self.white_pins,
+            black_pins: self.black_pins,
+            checkers: self.checkers,
+            last_move: self.last_move,
+            halfmove_clock,
+            fullmove_number,
+            hash,
+        };
         //updating the attacked squares also updates the pins
         if self.is_white_turn {
             self.white_pins = Bitboard::empty();
@@ -265,57 +404,46 @@ impl Chess {
         //update bitboard of the moved piece side attack (if white moves, update white. that way black will already be updated from last move)
         self.update_attacked_squares();
         self.is_white_turn = !self.is_white_turn;
-        self.last_move = (from, to);
-        ChessMove {
-            from,
-            to,
-            castle_flag,
-            en_passant_flag,
-            captured_piece,
-            last_king_placement: king_loc,
-            rook_cancel,
-        }
+        self.last_move = Move::new(from, to, flags);
+        debug_assert_eq!(self.hash, self.compute_hash());
+        chess_move
     }
     pub fn undo_move(&mut self, chess_move: ChessMove) {
-        self.board[chess_move.from as usize] = self.board[chess_move.to as usize];
-        self.board[chess_move.to as usize] = chess_move.captured_piece;
-        if chess_move.rook_cancel != 4 {
-            self.castling[chess_move.rook_cancel as usize] = true;
-        }
-        self.en_passant = 64;
         self.is_white_turn = !self.is_white_turn;
-        if chess_move.castle_flag {
-            if self.king_loc() == 62 {
-                self.castling[0] = true;
-                self.board[61] = Piece::Empty;
-                self.board[63] = Piece::Wrook;
-            } else if self.king_loc() == 58 {
-                self.castling[1] = true;
-                self.board[59] = Piece::Empty;
-                self.board[56] = Piece::Wrook;
-            } else if self.king_loc() == 6 {
-                self.castling[2] = true;
-                self.board[5] = Piece::Empty;
-                self.board[7] = Piece::Brook;
-            } else if self.king_loc() == 2 {
-                self.castling[3] = true;
-                self.board[3] = Piece::Empty;
-                self.board[0] = Piece::Brook;
-            } else {
-                panic!(
-                    "failed to revert castling {} {}",
-                    self.king_loc(),
-                    self.is_white_turn
-                )
-            }
-        } else if chess_move.en_passant_flag {
-            self.board[chess_move.to as usize] = Piece::Empty;
+        //putting back the moved piece also reverts promotions
+        self.set_square(chess_move.from, chess_move.moved_piece);
+        if chess_move.en_passant_flag {
+            self.set_square(chess_move.to, Piece::Empty);
             if self.is_white_turn {
-                self.en_passant = chess_move.to;
-                self.board[chess_move.to as usize + 8] = Piece::Bpawn;
+                self.set_square(chess_move.to + 8, chess_move.captured_piece);
             } else {
-                self.en_passant = chess_move.to;
-                self.board[chess_move.to as usize - 8] = Piece::Wpawn;
+                self.set_square(chess_move.to - 8, chess_move.captured_piece);
+            }
+        } else {
+            self.set_square(chess_move.to, chess_move.captured_piece);
+        }
+        if chess_move.castle_flag {
+            match chess_move.to {
+                62 => {
+                    self.set_square(61, Piece::Empty);
+                    self.set_square(63, Piece::Wrook);
+                }
+                58 => {
+                    self.set_square(59, Piece::Empty);
+                    self.set_square(56, Piece::Wrook);
+                }
+                6 => {
+                    self.set_square(5, Piece::Empty);
+                    self.set_square(7, Piece::Brook);
+                }
+                2 => {
+                    self.set_square(3, Piece::Empty);
+                    self.set_square(0, Piece::Brook);
+                }
+                _ => panic!(
+                    "failed to revert castling {} {}",
+                    chess_move.to, self.is_white_turn
+                ),
             }
         }
         if self.is_white_turn {
@@ -323,15 +451,36 @@ impl Chess {
         } else {
             self.black_king = chess_move.last_king_placement;
         }
+        self.castling = chess_move.castling;
+        self.en_passant = chess_move.en_passant;
+        self.white_attack = chess_move.white_attack;
+        self.black_attack = chess_move.black_attack;
+        self.white_pins = chess_move.white_pins;
+        self.black_pins = chess_move.black_pins;
+        self.checkers = chess_move.checkers;
+        self.last_move = chess_move.last_move;
+        self.halfmove_clock = chess_move.halfmove_clock;
+        self.fullmove_number = chess_move.fullmove_number;
+        self.hash = chess_move.hash;
+        self.history.pop();
+        debug_assert_eq!(self.hash, self.compute_hash());
+    }
+    //rebuilds both attack maps and pin boards from scratch, leaving the checkers of the side to move
+    pub fn refresh_attacks(&mut self) {
+        let side = self.is_white_turn;
+        self.white_pins = Bitboard::empty();
+        self.black_pins = Bitboard::empty();
+        self.update_attacked_squares();
+        self.is_white_turn = !side;
+        self.update_attacked_squares();
+        self.is_white_turn = side;
     }
     pub fn update_attacked_squares(&mut self) {
-        self.checking_pieces = Vec::new();
+        self.checkers = Bitboard::empty();
         let mut attacked_squares = Bitboard::empty();
-        for (i, piece) in self.board.into_iter().enumerate() {
-            if piece.is_white() == self.is_white_turn && piece != Piece::Empty {
-                // we want all moves, even if they cant actually move there
-                attacked_squares.switch_on_indices(&self.generate_attacks(i));
-            }
+        for i in self.side_pieces(self.is_white_turn) {
+            // we want all moves, even if they cant actually move there
+            attacked_squares |= self.generate_attacks(i as usize);
         }
         if self.is_white_turn {
             self.white_attack = attacked_squares;
@@ -339,31 +488,38 @@ impl Chess {
             self.black_attack = attacked_squares;
         }
     }
-    pub fn gen_moves(&mut self, index: usize) -> Vec<u8> {
+    //adds the pseudo legal moves of the piece on index
+    pub fn gen_moves(&self, index: usize, moves: &mut MoveList) {
         match self.board[index] {
-            Piece::Wking | Piece::Bking => self.gen_moves_king(index),
+            Piece::Wking | Piece::Bking => self.gen_moves_king(index, moves),
             Piece::Wqueen | Piece::Bqueen => {
-                let mut moves = self.gen_moves_rook(index);
-                moves.extend(self.gen_moves_bishop(index));
-                moves
+                self.gen_moves_rook(index, moves);
+                self.gen_moves_bishop(index, moves);
             }
-            Piece::Wrook | Piece::Brook => self.gen_moves_rook(index),
-            Piece::Wbishop | Piece::Bbishop => self.gen_moves_bishop(index),
-            Piece::Wknight | Piece::Bknight => self.gen_moves_knight(index),
-            Piece::Wpawn | Piece::Bpawn => self.gen_moves_pawn(index),
-            _ => Vec::new(),
+            Piece::Wrook | Piece::Brook => self.gen_moves_rook(index, moves),
+            Piece::Wbishop | Piece::Bbishop => self.gen_moves_bishop(index, moves),
+            Piece::Wknight | Piece::Bknight => self.gen_moves_knight(index, moves),
+            Piece::Wpawn | Piece::Bpawn => self.gen_moves_pawn(index, moves),
+            _ => (),
         }
     }
-    pub fn get_legals(&mut self, index: usize) {
-        self.moves = self
-            .gen_moves(index)
-            .iter()
-            .filter(|&&move_index| self.is_legal(index as u8, move_index))
-            .cloned()
-            .collect()
+    //legal moves of the piece on index
+    pub fn get_legals(&self, index: usize) -> MoveList {
+        let mut moves = MoveList::new();
+        self.gen_moves(index, &mut moves);
+        moves.retain(|legal_move| self.is_legal(legal_move.from(), legal_move.to()));
+        moves
+    }
+    //a capture if an enemy piece stands on to
+    fn push_move(&self, moves: &mut MoveList, from: usize, to: u8) {
+        let flags = if self.board[to as usize] == Piece::Empty {
+            Move::QUIET
+        } else {
+            Move::CAPTURE
+        };
+        moves.push(Move::new(from as u8, to, flags));
     }
-    pub fn gen_castling_king(&self, index: usize) -> Vec<u8> {
-        let mut moves = vec![];
+    pub fn gen_castling_king(&self, index: usize, moves: &mut MoveList) {
         let piece = self.board[index];
         if !piece.is_white() {
             if self.castling[2]
@@ -374,7 +530,7 @@ impl Chess {
                 && !self.is_check(5)
                 && !self.is_check(6)
             {
-                moves.push(6);
+                moves.push(Move::new(4, 6, Move::KING_CASTLE));
             }
             if self.castling[3]
                 && self.board[3] == Piece::Empty
@@ -385,7 +541,7 @@ impl Chess {
                 && !self.is_check(3)
                 && !self.is_check(2)
             {
-                moves.push(2);
+                moves.push(Move::new(4, 2, Move::QUEEN_CASTLE));
             }
         } else {
             if self.castling[0]
@@ -396,7 +552,7 @@ impl Chess {
                 && !self.is_check(61)
                 && !self.is_check(62)
             {
-                moves.push(62);
+                moves.push(Move::new(60, 62, Move::KING_CASTLE));
             }
             if self.castling[1]
                 && self.board[59] == Piece::Empty
@@ -407,557 +563,174 @@ impl Chess {
                 && !self.is_check(59)
                 && !self.is_check(58)
             {
-                moves.push(58);
+                moves.push(Move::new(60, 58, Move::QUEEN_CASTLE));
             }
         }
-        moves
     }
-    pub fn gen_moves_rook(&self, index: usize) -> Vec<u8> {
-        let mut moves: Vec<u8> = Vec::new();
-        let piece = self.board[index];
-        let row = index as u8 / 8;
-        let col = index as u8 % 8;
-        // Check horizontally to the right
-        for c in (col + 1)..8 {
-            let new_index = row * 8 + c;
-            let new_piece = self.board[new_index as usize];
-            if new_piece == Piece::Empty {
-                moves.push(new_index);
-            } else if self.is_opponent_piece(new_piece, piece) {
-                moves.push(new_index);
-                break;
-            } else {
-                break;
-            }
-        }
-        // Check horizontally to the left
-        for c in (0..col).rev() {
-            let new_index = row * 8 + c;
-            let new_piece = self.board[new_index as usize];
-            if new_piece == Piece::Empty {
-                moves.push(new_index);
-            } else if self.is_opponent_piece(new_piece, piece) {
-                moves.push(new_index);
-                break;
-            } else {
-                break;
-            }
-        }
-        // Check vertically upwards
-        for r in (0..row).rev() {
-            let new_index = r * 8 + col;
-            let new_piece = self.board[new_index as usize];
-            if new_piece == Piece::Empty {
-                moves.push(new_index);
-            } else if self.is_opponent_piece(new_piece, piece) {
-                moves.push(new_index);
-                break;
-            } else {
-                break;
-            }
-        }
-        // Check vertically downwards
-        for r in (row + 1)..8 {
-            let new_index = r * 8 + col;
-            let new_piece = self.board[new_index as usize];
-            if self.board[new_index as usize] == Piece::Empty {
-                moves.push(new_index);
-            } else if self.is_opponent_piece(new_piece, piece) {
-                moves.push(new_index);
-                break;
-            } else {
-                break;
-            }
+    pub fn gen_moves_rook(&self, index: usize, moves: &mut MoveList) {
+        let own = self.side_pieces(self.board[index].is_white());
+        for to in magic::rook_attacks(index as u8, self.occupied()) & !own {
+            self.push_move(moves, index, to);
         }
-        moves
     }
-    pub fn gen_moves_bishop(&self, index: usize) -> Vec<u8> {
-        let mut moves: Vec<u8> = Vec::new();
-        let piece = self.board[index];
-        let row = index as u8 / 8;
-        let col = index as u8 % 8;
-        // Define the possible bishop moves in terms of row and column offsets
-        let offsets: [(i32, i32); 4] = [
-            (-1, -1), // Up-left
-            (-1, 1),  // Up-right
-            (1, -1),  // Down-left
-            (1, 1),   // Down-right
-        ];
-        // Generate potential moves for each offset
-        for &(row_offset, col_offset) in offsets.iter() {
-            let mut new_row = row as i32 + row_offset;
-            let mut new_col = col as i32 + col_offset;
-            // Keep moving in the diagonal direction until out of bounds or blocked
-            while (0..8).contains(&new_row) && (0..8).contains(&new_col) {
-                let new_index = (new_row * 8 + new_col) as u8;
-                let new_piece = self.board[new_index as usize];
-                if new_piece == Piece::Empty {
-                    moves.push(new_index);
-                } else if self.is_opponent_piece(new_piece, piece) {
-                    moves.push(new_index);
-                    break;
-                } else {
-                    break;
-                }
-                new_row += row_offset;
-                new_col += col_offset;
-            }
+    pub fn gen_moves_bishop(&self, index: usize, moves: &mut MoveList) {
+        let own = self.side_pieces(self.board[index].is_white());
+        for to in magic::bishop_attacks(index as u8, self.occupied()) & !own {
+            self.push_move(moves, index, to);
         }
-        moves
     }
-    pub fn generate_attacks(&mut self, index: usize) -> Vec<u8> {
+    pub fn generate_attacks(&mut self, index: usize) -> Bitboard {
         match self.board[index] {
             Piece::Wking | Piece::Bking => self.gen_attacks_king(index),
             Piece::Wqueen | Piece::Bqueen => {
-                let mut moves = self.gen_attacks_rook(index as u8);
-                moves.extend(self.gen_attacks_bishop(index as u8));
-                moves
+                self.gen_attacks_rook(index as u8) | self.gen_attacks_bishop(index as u8)
             }
             Piece::Wrook | Piece::Brook => self.gen_attacks_rook(index as u8),
             Piece::Wbishop | Piece::Bbishop => self.gen_attacks_bishop(index as u8),
             Piece::Wknight | Piece::Bknight => self.gen_attacks_knight(index),
             Piece::Wpawn | Piece::Bpawn => self.gen_attacks_pawn(index),
-            _ => Vec::new(),
+            _ => Bitboard::empty(),
         }
     }
-    pub fn gen_attacks_rook(&mut self, index: u8) -> Vec<u8> {
-        let row = index / 8;
-        let col = index % 8;
-        let mut pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        let mut moves: Vec<u8> = Vec::new();
-        // Check horizontally to the right
-        let mut piece_count = 0;
-        let mut met_king = false;
-        for c in (col + 1)..8 {
-            let new_index = row * 8 + c;
-            let new_piece = self.board[new_index as usize];
-            if new_piece == Piece::Empty && piece_count == 0 {
-                moves.push(new_index);
-                pin_squares.switch_on_index(new_index);
-            } else if new_piece.is_white() != self.is_white_turn {
-                // Enemy piece
-                if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                    if piece_count == 0 {
-                        moves.push(new_index);
-                        if new_index < 63 {
-                            moves.push(new_index + 1);
-                        }
-                        self.checking_pieces.push(index);
-                    }
-                    pin_squares.switch_on_index(new_index);
-                    met_king = true;
-                    break; // No need to consider further moves in this direction
-                }
-                piece_count += 1;
-                pin_squares.switch_on_index(new_index);
-                if piece_count == 1 {
-                    moves.push(new_index);
-                }
-            } else {
-                // Friendly piece
-                if piece_count == 0 {
-                    moves.push(new_index);
-                }
-                break; // Stop considering further moves in this direction
-            }
-        }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
-            }
-        }
-
-        // Check horizontally to the left
-        pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        piece_count = 0;
-        met_king = false;
-        for c in (0..col).rev() {
-            let new_index = row * 8 + c;
-            let new_piece = self.board[new_index as usize];
-            if new_piece == Piece::Empty && piece_count == 0 {
-                moves.push(new_index);
-                pin_squares.switch_on_index(new_index);
-            } else if new_piece.is_white() != self.is_white_turn {
-                // Enemy piece
-                if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                    if piece_count == 0 {
-                        moves.push(new_index);
-                        if new_index >= 1 {
-                            moves.push(new_index - 1);
-                        }
-                        self.checking_pieces.push(index);
-                    }
-                    pin_squares.switch_on_index(new_index);
-                    met_king = true;
-                    break; // No need to consider further moves in this direction
-                }
-                piece_count += 1;
-                pin_squares.switch_on_index(new_index);
-                if piece_count == 1 {
-                    moves.push(new_index);
-                }
-            } else {
-                // Friendly piece
-                if piece_count == 0 {
-                    moves.push(new_index);
-                }
-                break; // Stop considering further moves in this direction
-            }
-        }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
-            }
-        }
-
-        // Check vertically upwards
-        pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        piece_count = 0;
-        met_king = false;
-        for r in (0..row).rev() {
-            let new_index = r * 8 + col;
-            let new_piece = self.board[new_index as usize];
-            if new_piece == Piece::Empty && piece_count == 0 {
-                moves.push(new_index);
-                pin_squares.switch_on_index(new_index);
-            } else if new_piece.is_white() != self.is_white_turn {
-                // Enemy piece
-                if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                    if piece_count == 0 {
-                        moves.push(new_index);
-                        if new_index >= 8 {
-                            moves.push(new_index - 8);
-                        }
-                        self.checking_pieces.push(index);
-                    }
-                    pin_squares.switch_on_index(new_index);
-                    met_king = true;
-                    break; // No need to consider further moves in this direction
-                }
-                piece_count += 1;
-                pin_squares.switch_on_index(new_index);
-                if piece_count == 1 {
-                    moves.push(new_index);
-                }
-            } else {
-                // Friendly piece
-                if piece_count == 0 {
-                    moves.push(new_index);
-                }
-                break; // Stop considering further moves in this direction
-            }
-        }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
-            }
-        }
-
-        // Check vertically downwards
-        pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        piece_count = 0;
-        met_king = false;
-        for r in (row + 1)..8 {
-            let new_index = r * 8 + col;
-            let new_piece = self.board[new_index as usize];
-            if new_piece == Piece::Empty && piece_count == 0 {
-                moves.push(new_index);
-                pin_squares.switch_on_index(new_index);
-            } else if new_piece.is_white() != self.is_white_turn {
-                // Enemy piece
-                if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                    if piece_count == 0 {
-                        moves.push(new_index);
-                        if new_index < 56 {
-                            moves.push(new_index + 8);
-                        }
-                        self.checking_pieces.push(index);
-                    }
-                    pin_squares.switch_on_index(new_index);
-                    met_king = true;
-                    break; // No need to consider further moves in this direction
-                }
-                piece_count += 1;
-                pin_squares.switch_on_index(new_index);
-                if piece_count == 1 {
-                    moves.push(new_index);
-                }
-            } else {
-                // Friendly piece
-                if piece_count == 0 {
-                    moves.push(new_index);
-                }
-                break; // Stop considering further moves in this direction
-            }
-        }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
-            }
-        }
-        moves
+    pub fn gen_attacks_rook(&mut self, index: u8) -> Bitboard {
+        self.gen_attacks_slider(index, magic::rook_attacks)
     }
-    pub fn gen_attacks_bishop(&mut self, index: u8) -> Vec<u8> {
-        let row = index / 8;
-        let col = index % 8;
-        let mut pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        let mut moves: Vec<u8> = Vec::new();
-
-        // Check diagonally to the top-right
-        let mut piece_count = 0;
-        let mut met_king = false;
-        for i in 1..8 {
-            let r = row as i32 + i;
-            let c = col as i32 + i;
-            if (0..8).contains(&r) && (0..8).contains(&c) {
-                let new_index = (r * 8 + c) as usize;
-                let new_piece = self.board[new_index];
-                if new_piece == Piece::Empty && piece_count == 0 {
-                    moves.push(new_index as u8);
-                    pin_squares.switch_on_index(new_index as u8);
-                } else if new_piece.is_white() != self.is_white_turn {
-                    // Enemy piece
-                    if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                        if piece_count == 0 {
-                            moves.push(new_index as u8);
-                            if new_index < 55 {
-                                moves.push(new_index as u8 + 9);
-                            }
-                            self.checking_pieces.push(index);
-                        }
-                        pin_squares.switch_on_index(new_index as u8);
-                        met_king = true;
-                        break; // No need to consider further moves in this direction
-                    }
-                    piece_count += 1;
-                    pin_squares.switch_on_index(new_index as u8);
-                    if piece_count == 1 {
-                        moves.push(new_index as u8);
-                    }
+    pub fn gen_attacks_bishop(&mut self, index: u8) -> Bitboard {
+        self.gen_attacks_slider(index, magic::bishop_attacks)
+    }
+    //attacks of a slider of the side to move, also noting checks and pins against the enemy king
+    fn gen_attacks_slider(
+        &mut self,
+        index: u8,
+        attacks_from: fn(u8, Bitboard) -> Bitboard,
+    ) -> Bitboard {
+        let enemy_king = if self.is_white_turn {
+            self.black_king
+        } else {
+            self.white_king
+        };
+        let mut king = Bitboard::empty();
+        king.set_bit(enemy_king);
+        //the ray goes on behind the king, so it can't step back along it
+        let attacks = attacks_from(index, self.occupied() & !king);
+        if attacks.get_bit(enemy_king) {
+            self.checkers.set_bit(index);
+        } else if attacks_from(index, king).get_bit(enemy_king) {
+            //on a line with the king: a single enemy piece in between is pinned
+            let blockers = Bitboard::between(index, enemy_king) & self.occupied();
+            if blockers.popcount() == 1
+                && (blockers & self.side_pieces(self.is_white_turn)).is_empty()
+            {
+                if self.is_white_turn {
+                    self.white_pins |= blockers;
                 } else {
-                    // Friendly piece
-                    if piece_count == 0 {
-                        moves.push(new_index as u8);
-                    }
-                    break; // Stop considering further moves in this direction
+                    self.black_pins |= blockers;
                 }
-            } else {
-                break; // Out of board boundaries
             }
         }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
-            }
+        attacks
+    }
+    //FIDE dead positions: K vs K, K+minor vs K, and kings with bishops all on one square colour
+    pub fn is_insufficient_material(&self) -> bool {
+        //pawns, rooks and queens can always mate
+        let majors = [
+            Piece::Wpawn,
+            Piece::Bpawn,
+            Piece::Wrook,
+            Piece::Brook,
+            Piece::Wqueen,
+            Piece::Bqueen,
+        ];
+        if majors.iter().any(|&piece| !self.bitboard(piece).is_empty()) {
+            return false;
         }
+        let knights = self.bitboard(Piece::Wknight) | self.bitboard(Piece::Bknight);
+        let bishops = self.bitboard(Piece::Wbishop) | self.bitboard(Piece::Bbishop);
+        (knights | bishops).popcount() <= 1
+            || (knights.is_empty()
+                && ((bishops & LIGHT_SQUARES).is_empty() || (bishops & !LIGHT_SQUARES).is_empty()))
+    }
 
-        // Check diagonally to the top-left
-        pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        piece_count = 0;
-        met_king = false;
-        for i in 1..8 {
-            let r = row as i32 + i;
-            let c = col as i32 - i;
-            if (0..8).contains(&r) && (0..8).contains(&c) {
-                let new_index = (r * 8 + c) as usize;
-                let new_piece = self.board[new_index];
-                if new_piece == Piece::Empty && piece_count == 0 {
-                    moves.push(new_index as u8);
-                    pin_squares.switch_on_index(new_index as u8);
-                } else if new_piece.is_white() != self.is_white_turn {
-                    // Enemy piece
-                    if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                        if piece_count == 0 {
-                            if new_index < 57 {
-                                moves.push(new_index as u8 + 7);
-                            }
-                            moves.push(new_index as u8);
-                            self.checking_pieces.push(index);
-                        }
-                        pin_squares.switch_on_index(new_index as u8);
-                        met_king = true;
-                        break; // No need to consider further moves in this direction
-                    }
-                    piece_count += 1;
-                    pin_squares.switch_on_index(new_index as u8);
-                    if piece_count == 1 {
-                        moves.push(new_index as u8);
-                    }
-                } else {
-                    // Friendly piece
-                    if piece_count == 0 {
-                        moves.push(new_index as u8);
-                    }
-                    break; // Stop considering further moves in this direction
-                }
-            } else {
-                break; // Out of board boundaries
-            }
-        }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
-            }
+    //how many times the current position appeared before, only looking back to the last capture or pawn move
+    pub fn repetitions(&self) -> usize {
+        self.history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1) //the position before the last move had the other side to move
+            .step_by(2)
+            .filter(|&&hash| hash == self.hash)
+            .count()
+    }
+    // Check for threefold repetition
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetitions() >= 2
+    }
+    pub fn get_all_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        for i in self.side_pieces(self.is_white_turn) {
+            self.gen_moves(i as usize, &mut moves);
         }
-
-        // Check diagonally to the bottom-right
-        pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        piece_count = 0;
-        met_king = false;
-        for i in 1..8 {
-            let r = row as i32 - i;
-            let c = col as i32 + i;
-            if (0..8).contains(&r) && (0..8).contains(&c) {
-                let new_index = (r * 8 + c) as usize;
-                let new_piece = self.board[new_index];
-                if new_piece == Piece::Empty && piece_count == 0 {
-                    moves.push(new_index as u8);
-                    pin_squares.switch_on_index(new_index as u8);
-                } else if new_piece.is_white() != self.is_white_turn {
-                    // Enemy piece
-                    if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                        if piece_count == 0 {
-                            moves.push(new_index as u8);
-                            if new_index >= 7 {
-                                moves.push(new_index as u8 - 7);
-                            }
-                            self.checking_pieces.push(index);
-                        }
-                        pin_squares.switch_on_index(new_index as u8);
-                        met_king = true;
-                        break; // No need to consider further moves in this direction
-                    }
-                    piece_count += 1;
-                    pin_squares.switch_on_index(new_index as u8);
-                    if piece_count == 1 {
-                        moves.push(new_index as u8);
-                    }
-                } else {
-                    // Friendly piece
-                    if piece_count == 0 {
-                        moves.push(new_index as u8);
-                    }
-                    break; // Stop considering further moves in this direction
+        moves.retain(|legal_move| self.is_legal(legal_move.from(), legal_move.to()));
+        moves
+    }
+    //the legal captures and promotions, what the quiescence search resolves
+    pub fn get_captures(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        let enemy = self.side_pieces(!self.is_white_turn);
+        let occupied = self.occupied();
+        for from in self.side_pieces(self.is_white_turn) {
+            let index = from as usize;
+            let targets = match self.board[index] {
+                Piece::Wking | Piece::Bking => Self::leaps(&self.king_moves[index]),
+                Piece::Wknight | Piece::Bknight => Self::leaps(&self.knight_moves[index]),
+                Piece::Wqueen | Piece::Bqueen => {
+                    magic::rook_attacks(from, occupied) | magic::bishop_attacks(from, occupied)
                 }
-            } else {
-                break; // Out of board boundaries
-            }
-        }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
-            }
-        }
-
-        // Check diagonally to the bottom-left
-        pin_squares = Bitboard::empty();
-        pin_squares.set_bit(index);
-        piece_count = 0;
-        met_king = false;
-        for i in 1..8 {
-            let r = row as i32 - i;
-            let c = col as i32 - i;
-            if (0..8).contains(&r) && (0..8).contains(&c) {
-                let new_index = (r * 8 + c) as usize;
-                let new_piece = self.board[new_index];
-                if new_piece == Piece::Empty && piece_count == 0 {
-                    moves.push(new_index as u8);
-                    pin_squares.switch_on_index(new_index as u8);
-                } else if new_piece.is_white() != self.is_white_turn {
-                    // Enemy piece
-                    if new_piece == Piece::Bking || new_piece == Piece::Wking {
-                        if piece_count == 0 {
-                            moves.push(new_index as u8);
-                            if new_index >= 9 {
-                                moves.push(new_index as u8 - 9);
-                            }
-                            self.checking_pieces.push(index);
-                        }
-                        pin_squares.switch_on_index(new_index as u8);
-                        met_king = true;
-                        break; // No need to consider further moves in this direction
-                    }
-                    piece_count += 1;
-                    pin_squares.switch_on_index(new_index as u8);
-                    if piece_count == 1 {
-                        moves.push(new_index as u8);
-                    }
-                } else {
-                    // Friendly piece
-                    if piece_count == 0 {
-                        moves.push(new_index as u8);
-                    }
-                    break; // Stop considering further moves in this direction
+                Piece::Wrook | Piece::Brook => magic::rook_attacks(from, occupied),
+                Piece::Wbishop | Piece::Bbishop => magic::bishop_attacks(from, occupied),
+                //few enough moves that filtering them below is cheap
+                _ => {
+                    self.gen_moves_pawn(index, &mut moves);
+                    continue;
                 }
-            } else {
-                break; // Out of board boundaries
-            }
-        }
-        if piece_count == 1 && met_king {
-            //only valid when an enemy piece is pinned to the king
-            if self.is_white_turn {
-                self.white_pins.0 |= pin_squares.0;
-            } else {
-                self.black_pins.0 |= pin_squares.0;
+            };
+            for to in targets & enemy {
+                moves.push(Move::new(from, to, Move::CAPTURE));
             }
         }
-
-        // Convert the Bitboard to a Vec<usize> for output
+        moves.retain(|legal_move| {
+            (legal_move.is_capture() || legal_move.promotion().is_some())
+                && self.is_legal(legal_move.from(), legal_move.to())
+        });
         moves
     }
-    pub fn is_insufficient_material(&self) -> bool {
-        // Implement your logic to check for insufficient material here
-        // Return true if the game is in a draw due to insufficient material
-        false
+    //the squares of a precomputed knight or king table as a bitboard, 64 marks no square
+    fn leaps(squares: &[u8; 8]) -> Bitboard {
+        let mut leaps = Bitboard::empty();
+        for &square in squares.iter().filter(|&&square| square != 64) {
+            leaps.set_bit(square);
+        }
+        leaps
     }
-
-    // Check for threefold repetition
-    pub fn is_threefold_repetition(&self) -> bool {
-        // Implement your logic to check for threefold repetition here
-        // Return true if the game is in a draw due to threefold repetition
-        false
-    }
-    pub fn get_all_moves(&mut self) -> Vec<(u8, u8)> {
-        let mut moves: Vec<(u8, u8)> = Vec::new();
-        for i in 0..64 {
-            if self.board[i].is_white() == self.is_white_turn {
-                self.get_legals(i);
-                moves.extend(
-                    std::mem::take(&mut self.moves)
-                        .into_iter()
-                        .map(|x| (i as u8, x)),
-                );
-            }
+    //plays a move from get_all_moves
+    pub fn make_move(&mut self, legal_move: Move) -> ChessMove {
+        self.move_piece(legal_move.from(), legal_move.to(), legal_move.promotion())
+    }
+    pub fn is_promotion(&self, from: u8, to: u8) -> bool {
+        match self.board[from as usize] {
+            Piece::Wpawn => to < 8,
+            Piece::Bpawn => to >= 56,
+            _ => false,
+        }
+    }
+    //pieces the side to move may promote to, best first
+    pub fn promotion_pieces(&self) -> [Piece; 4] {
+        if self.is_white_turn {
+            [Piece::Wqueen, Piece::Wrook, Piece::Wbishop, Piece::Wknight]
+        } else {
+            [Piece::Bqueen, Piece::Brook, Piece::Bbishop, Piece::Bknight]
         }
-        moves
     }
     pub fn precompute_knight() -> [[u8; 8]; 64] {
         let mut precomputed_moves: [[u8; 8]; 64] = [[64; 8]; 64];
@@ -986,25 +759,27 @@ impl Chess {
         }
         precomputed_moves
     }
-    pub fn gen_moves_knight(&self, index: usize) -> Vec<u8> {
-        self.knight_moves[index]
-            .into_iter()
-            .filter(|&i| i != 64 && self.board[i as usize].is_opponent_or_empty(self.board[index]))
-            .collect()
-    }
-    pub fn gen_attacks_knight(&mut self, index: usize) -> Vec<u8> {
-        self.knight_moves[index]
-            .into_iter()
-            .filter(|&i| i != 64)
-            .map(|i| {
-                if (self.board[index].is_white() && self.board[i as usize] == Piece::Bking)
-                    || (!self.board[index].is_white() && self.board[i as usize] == Piece::Wking)
-                {
-                    self.checking_pieces.push(index as u8)
-                }
-                i
-            })
-            .collect()
+    pub fn gen_moves_knight(&self, index: usize, moves: &mut MoveList) {
+        for i in self.knight_moves[index] {
+            if i != 64 && self.board[i as usize].is_opponent_or_empty(self.board[index]) {
+                self.push_move(moves, index, i);
+            }
+        }
+    }
+    pub fn gen_attacks_knight(&mut self, index: usize) -> Bitboard {
+        let mut attacks = Bitboard::empty();
+        for i in self.knight_moves[index] {
+            if i == 64 {
+                continue;
+            }
+            attacks.set_bit(i);
+            if (self.board[index].is_white() && self.board[i as usize] == Piece::Bking)
+                || (!self.board[index].is_white() && self.board[i as usize] == Piece::Wking)
+            {
+                self.checkers.set_bit(index as u8);
+            }
+        }
+        attacks
     }
     pub fn precompute_pawn() -> [[u8; 4]; 128] {
         //twice forward, once forward, left capture, right capture
@@ -1052,51 +827,63 @@ impl Chess {
         }
         precomputed_moves
     }
-    pub fn gen_moves_pawn(&self, index: usize) -> Vec<u8> {
-        let mut moves = Vec::new();
+    pub fn gen_moves_pawn(&self, index: usize, moves: &mut MoveList) {
         let color_offset = if self.board[index].is_white() { 0 } else { 64 };
         let pawn_moves = &self.pawn_moves[index + color_offset];
 
         // Check the first move (single square forward)
         if pawn_moves[1] != 64 && self.board[pawn_moves[1] as usize] == Piece::Empty {
-            moves.push(pawn_moves[1]);
+            self.push_pawn_move(moves, index, pawn_moves[1], false);
             // Check the second move (double square forward from starting position)
             if pawn_moves[0] != 64 && self.board[pawn_moves[0] as usize] == Piece::Empty {
-                moves.push(pawn_moves[0]);
+                moves.push(Move::new(index as u8, pawn_moves[0], Move::DOUBLE_PUSH));
             }
         }
         // Check the two diagonal capture moves
-        if pawn_moves[2] != 64
-            && (self.board[pawn_moves[2] as usize].is_opponent(self.board[index])
-                || self.en_passant == pawn_moves[2])
-        {
-            moves.push(pawn_moves[2]);
+        for &to in &pawn_moves[2..] {
+            if to == 64 {
+                continue;
+            }
+            if self.board[to as usize].is_opponent(self.board[index]) {
+                self.push_pawn_move(moves, index, to, true);
+            } else if self.en_passant == to {
+                moves.push(Move::new(index as u8, to, Move::EN_PASSANT));
+            }
         }
-        if pawn_moves[3] != 64
-            && (self.board[pawn_moves[3] as usize].is_opponent(self.board[index])
-                || self.en_passant == pawn_moves[3])
-        {
-            moves.push(pawn_moves[3]);
+    }
+    //one move per promotion piece when the pawn reaches the last rank, queen first
+    fn push_pawn_move(&self, moves: &mut MoveList, from: usize, to: u8, capture: bool) {
+        if self.is_promotion(from as u8, to) {
+            for piece in self.promotion_pieces() {
+                moves.push(Move::new(
+                    from as u8,
+                    to,
+                    Move::promotion_flags(piece, capture),
+                ));
+            }
+        } else if capture {
+            moves.push(Move::new(from as u8, to, Move::CAPTURE));
+        } else {
+            moves.push(Move::new(from as u8, to, Move::QUIET));
         }
-        moves
     }
-    pub fn gen_attacks_pawn(&mut self, index: usize) -> Vec<u8> {
+    pub fn gen_attacks_pawn(&mut self, index: usize) -> Bitboard {
         let pawn_moves =
             &self.pawn_moves[index + if self.board[index].is_white() { 0 } else { 64 }];
-        let mut attacking_moves = Vec::with_capacity(2);
+        let mut attacks = Bitboard::empty();
         for &move_index in pawn_moves.iter().skip(2) {
             if move_index < 64 {
-                attacking_moves.push(move_index);
+                attacks.set_bit(move_index);
                 if (self.board[index].is_white() && self.board[move_index as usize] == Piece::Bking)
                     || (!self.board[index].is_white()
                         && self.board[move_index as usize] == Piece::Wking)
                 {
-                    self.checking_pieces.push(index as u8);
+                    self.checkers.set_bit(index as u8);
                 }
             }
         }
-        attacking_moves
-    } // might be broken
+        attacks
+    }
     fn precompute_king() -> [[u8; 8]; 64] {
         let mut precomputed_moves: [[u8; 8]; 64] = [[64; 8]; 64];
         let offsets: [(i32, i32); 8] = [
@@ -1123,36 +910,98 @@ impl Chess {
         }
         precomputed_moves
     }
-    pub fn gen_moves_king(&self, index: usize) -> Vec<u8> {
-        let mut moves = self.gen_castling_king(index);
-        moves.extend(self.king_moves[index].into_iter().filter(|&i| {
-            i != 64 && self.board[i as usize].is_opponent_or_empty(self.board[index])
-        }));
-        moves
+    pub fn gen_moves_king(&self, index: usize, moves: &mut MoveList) {
+        self.gen_castling_king(index, moves);
+        for i in self.king_moves[index] {
+            if i != 64 && self.board[i as usize].is_opponent_or_empty(self.board[index]) {
+                self.push_move(moves, index, i);
+            }
+        }
     }
-    pub fn gen_attacks_king(&mut self, index: usize) -> Vec<u8> {
-        self.king_moves[index]
-            .into_iter()
-            .filter(|&i| i != 64)
-            .collect()
+    pub fn gen_attacks_king(&mut self, index: usize) -> Bitboard {
+        let mut attacks = Bitboard::empty();
+        for i in self.king_moves[index] {
+            if i != 64 {
+                attacks.set_bit(i);
+            }
+        }
+        attacks
     }
-    pub fn is_ending(&mut self) -> i32 {
+    pub fn is_ending(&mut self) -> GameStatus {
         if self.get_all_moves().is_empty() {
             if self.is_check(self.king_loc()) {
-                if self.is_white_turn {
-                    -1
-                } else {
-                    1
+                GameStatus::Checkmate {
+                    winner: self.side_to_move().opponent(),
                 }
             } else {
-                0
+                GameStatus::Stalemate
             }
         } else if self.is_insufficient_material() {
-            3
+            GameStatus::InsufficientMaterial
         } else if self.is_threefold_repetition() {
-            4
+            GameStatus::Repetition
+        } else if self.halfmove_clock >= 150 {
+            //seventy-five move rule ends the game without a claim
+            GameStatus::FiftyMove
+        } else {
+            GameStatus::Ongoing {
+                side: self.side_to_move(),
+            }
+        }
+    }
+    pub fn side_to_move(&self) -> Side {
+        if self.is_white_turn {
+            Side::White
         } else {
-            2
+            Side::Black
+        }
+    }
+    //fifty moves by each side without a capture or pawn move
+    pub fn can_claim_fifty_move(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    White,
+    Black,
+}
+
+impl Side {
+    pub fn opponent(self) -> Side {
+        match self {
+            Side::White => Side::Black,
+            Side::Black => Side::White,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing { side: Side },
+    Checkmate { winner: Side },
+    Stalemate,
+    InsufficientMaterial,
+    Repetition,
+    FiftyMove, // claimed after 50 moves or automatic after 75
+    //nothing in the engine or the GUI ends a game these ways, they are only here so results
+    //from a PGN's Result and Termination tags have somewhere to go
+    TimeForfeit { winner: Side },
+    Resignation { winner: Side },
+    Agreement,
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        !matches!(self, GameStatus::Ongoing { .. })
+    }
+    pub fn winner(&self) -> Option<Side> {
+        match *self {
+            GameStatus::Checkmate { winner }
+            | GameStatus::TimeForfeit { winner }
+            | GameStatus::Resignation { winner } => Some(winner),
+            _ => None,
         }
     }
 }
@@ -1176,6 +1025,25 @@ pub enum Piece {
 }
 
 impl Piece {
+    //every piece but Empty, in the order of their index
+    pub const ALL: [Piece; 12] = [
+        Piece::Bpawn,
+        Piece::Wpawn,
+        Piece::Bknight,
+        Piece::Wknight,
+        Piece::Bbishop,
+        Piece::Wbishop,
+        Piece::Brook,
+        Piece::Wrook,
+        Piece::Bqueen,
+        Piece::Wqueen,
+        Piece::Bking,
+        Piece::Wking,
+    ];
+    //slot of the piece in Chess::pieces and the zobrist keys, Empty has none
+    pub fn index(self) -> usize {
+        self as usize - 1
+    }
     pub fn is_white(&self) -> bool {
         matches!(
             self,
@@ -1187,19 +1055,54 @@ impl Piece {
                 | Piece::Wking
         )
     }
-    pub fn evaluate(&self) -> f32 {
+    pub fn from_char(c: char) -> Option<Piece> {
+        match c {
+            'P' => Some(Piece::Wpawn),
+            'N' => Some(Piece::Wknight),
+            'B' => Some(Piece::Wbishop),
+            'R' => Some(Piece::Wrook),
+            'Q' => Some(Piece::Wqueen),
+            'K' => Some(Piece::Wking),
+            'p' => Some(Piece::Bpawn),
+            'n' => Some(Piece::Bknight),
+            'b' => Some(Piece::Bbishop),
+            'r' => Some(Piece::Brook),
+            'q' => Some(Piece::Bqueen),
+            'k' => Some(Piece::Bking),
+            _ => None,
+        }
+    }
+    pub fn to_char(self) -> char {
+        match self {
+            Piece::Wpawn => 'P',
+            Piece::Wknight => 'N',
+            Piece::Wbishop => 'B',
+            Piece::Wrook => 'R',
+            Piece::Wqueen => 'Q',
+            Piece::Wking => 'K',
+            Piece::Bpawn => 'p',
+            Piece::Bknight => 'n',
+            Piece::Bbishop => 'b',
+            Piece::Brook => 'r',
+            Piece::Bqueen => 'q',
+            Piece::Bking => 'k',
+            Piece::Empty => '.',
+        }
+    }
+    pub fn evaluate(&self) -> i32 {
+        //in centipawns, positive for white
         match self {
-            Piece::Wpawn => 100.0,
-            Piece::Bpawn => -100.0,
-            Piece::Wknight => 300.0,
-            Piece::Bknight => -300.0,
-            Piece::Wbishop => 320.0,
-            Piece::Bbishop => -320.0,
-            Piece::Wrook => 500.0,
-            Piece::Brook => -500.0,
-            Piece::Wqueen => 900.0,
-            Piece::Bqueen => -900.0,
-            _ => 0.0,
+            Piece::Wpawn => 100,
+            Piece::Bpawn => -100,
+            Piece::Wknight => 300,
+            Piece::Bknight => -300,
+            Piece::Wbishop => 320,
+            Piece::Bbishop => -320,
+            Piece::Wrook => 500,
+            Piece::Brook => -500,
+            Piece::Wqueen => 900,
+            Piece::Bqueen => -900,
+            _ => 0,
         }
     }
     pub fn is_opponent_or_empty(&self, opp: Piece) -> bool {
@@ -1222,9 +1125,202 @@ impl Piece {
 pub struct ChessMove {
     pub from: u8,
     pub to: u8,
+    pub moved_piece: Piece, // the pawn, for promotions
     pub castle_flag: bool,
     pub en_passant_flag: bool,
     pub captured_piece: Piece, // Stores the captured piece, if any
     pub last_king_placement: u8,
-    pub rook_cancel: u8, // 0/1/2/3/4
+    // position state from before the move, restored as is by undo_move
+    pub castling: [bool; 4],
+    pub en_passant: u8,
+    pub white_attack: Bitboard,
+    pub black_attack: Bitboard,
+    pub white_pins: Bitboard,
+    pub black_pins: Bitboard,
+    checkers: Bitboard,
+    pub last_move: Move,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    pub hash: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perft::{walk, WALK_POSITIONS};
+
+    const KNIGHT_SHUFFLE: [(u8, u8); 4] = [(62, 45), (6, 21), (45, 62), (21, 6)];
+
+    #[test]
+    fn threefold_repetition() {
+        let mut chess = Chess::new();
+        for (from, to) in KNIGHT_SHUFFLE {
+            chess.move_piece(from, to, None);
+        }
+        assert_eq!(chess.repetitions(), 1);
+        assert!(!chess.is_threefold_repetition());
+        for (from, to) in KNIGHT_SHUFFLE {
+            chess.move_piece(from, to, None);
+        }
+        assert!(chess.is_threefold_repetition());
+        assert_eq!(chess.is_ending(), GameStatus::Repetition);
+    }
+
+    #[test]
+    fn repetition_resets_after_pawn_move() {
+        let mut chess = Chess::new();
+        for (from, to) in KNIGHT_SHUFFLE {
+            chess.move_piece(from, to, None);
+        }
+        chess.move_piece(52, 36, None);
+        chess.move_piece(12, 28, None);
+        for (from, to) in KNIGHT_SHUFFLE {
+            chess.move_piece(from, to, None);
+        }
+        assert_eq!(chess.repetitions(), 1);
+        let chess_move = chess.move_piece(62, 45, None);
+        chess.undo_move(chess_move);
+        assert_eq!(chess.repetitions(), 1);
+    }
+
+    #[test]
+    fn fifty_and_seventy_five_move_rules() {
+        let mut chess = Chess::from_fen("8/8/4k3/8/8/3K3R/8/8 w - - 99 80").unwrap();
+        assert!(!chess.can_claim_fifty_move());
+        let chess_move = chess.move_piece(47, 39, None);
+        assert!(chess.can_claim_fifty_move());
+        assert_eq!(chess.is_ending(), GameStatus::Ongoing { side: Side::Black });
+        chess.undo_move(chess_move);
+        assert_eq!(chess.halfmove_clock, 99);
+        chess.move_piece(47, 46, None);
+        chess.move_piece(20, 12, None); //no capture or pawn move, the clock keeps counting
+        assert_eq!(chess.halfmove_clock, 101);
+
+        let mut chess = Chess::from_fen("8/8/4k3/8/8/3K3R/8/8 w - - 149 105").unwrap();
+        chess.move_piece(47, 39, None);
+        assert_eq!(chess.is_ending(), GameStatus::FiftyMove);
+    }
+
+    fn insufficient(fen: &str) -> bool {
+        Chess::from_fen(fen).unwrap().is_insufficient_material()
+    }
+
+    #[test]
+    fn fide_insufficient_material() {
+        for fen in [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KN3/8/8 w - - 0 1",
+            "8/8/4kb2/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4kb2/8/8/3KB3/8/8 w - - 0 1", //both bishops on dark squares
+            "8/5b2/4k3/8/8/3K1B2/8/8 w - - 0 1", //both on light squares
+        ] {
+            assert!(insufficient(fen), "{}", fen);
+        }
+        for fen in [
+            "8/8/4k3/8/8/3KP3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K3R/8/8 w - - 0 1",
+            //none of these can force mate, but a mate can still come up on the board
+            "8/8/4kb2/8/8/3K1B2/8/8 w - - 0 1", //opposite coloured bishops
+            "8/8/4kn2/8/8/3KN3/8/8 w - - 0 1",
+            "8/8/4kn2/8/8/3KB3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KNN2/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KBN2/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3KBB2/8/8 w - - 0 1",
+        ] {
+            assert!(!insufficient(fen), "{}", fen);
+        }
+        assert_eq!(
+            Chess::from_fen("8/8/4k3/8/8/3KN3/8/8 w - - 0 1")
+                .unwrap()
+                .is_ending(),
+            GameStatus::InsufficientMaterial
+        );
+    }
+
+    #[test]
+    fn rook_captured_on_corner_loses_castling() {
+        let mut chess = Chess::from_fen("r3k2r/8/8/8/8/8/1B5r/R3K3 w Qkq - 0 1").unwrap();
+        chess.move_piece(49, 7, None); //Bxh8
+        chess.move_piece(55, 7, None); //Rxh8, another rook back on the corner
+        chess.move_piece(56, 48, None); //Ra2
+        assert!(!chess
+            .get_all_moves()
+            .contains(&Move::new(4, 6, Move::KING_CASTLE)));
+        assert_eq!(chess.to_fen(), "r3k2r/8/8/8/8/8/R7/4K3 b q - 1 2");
+    }
+
+    #[test]
+    fn captures_only() {
+        //every reachable position a few plies deep, against filtering the full move list
+        let by_squares =
+            |legal_move: &Move| (legal_move.from(), legal_move.to(), legal_move.flags());
+        for fen in WALK_POSITIONS {
+            walk(&mut Chess::from_fen(fen).unwrap(), 2, &mut |chess| {
+                let mut expected: Vec<Move> = chess
+                    .get_all_moves()
+                    .iter()
+                    .copied()
+                    .filter(|legal_move| {
+                        legal_move.is_capture() || legal_move.promotion().is_some()
+                    })
+                    .collect();
+                let mut captures = chess.get_captures().to_vec();
+                expected.sort_by_key(by_squares);
+                captures.sort_by_key(by_squares);
+                assert_eq!(captures, expected, "{}", chess.to_fen());
+            });
+        }
+    }
+
+    #[test]
+    fn undo_restores_position() {
+        let mut played = vec![];
+        for fen in [
+            //castling both ways for both sides, and captures
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            //promotions, with and without a capture, for both sides
+            "r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/1p6/R3K3 b - - 0 1",
+            //en passant for both sides
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "4k3/8/8/8/3pP3/8/8/4K3 b - e3 5 40",
+        ] {
+            let mut chess = Chess::from_fen(fen).unwrap();
+            for legal_move in chess.get_all_moves() {
+                let start = chess.clone();
+                let chess_move = chess.make_move(legal_move);
+                chess.undo_move(chess_move);
+                assert_eq!(chess, start, "{} after {}", fen, legal_move);
+                played.push(legal_move);
+            }
+        }
+        //every kind of move came up
+        assert!(played.iter().any(|m| m.flags() == Move::KING_CASTLE));
+        assert!(played.iter().any(|m| m.flags() == Move::QUEEN_CASTLE));
+        assert!(played.iter().any(|m| m.is_en_passant()));
+        assert!(played
+            .iter()
+            .any(|m| m.promotion().is_some() && m.is_capture()));
+        assert!(played
+            .iter()
+            .any(|m| m.promotion().is_some() && !m.is_capture()));
+        assert!(played.iter().any(|m| m.flags() == Move::CAPTURE));
+    }
+
+    #[test]
+    fn incremental_bitboards() {
+        for fen in WALK_POSITIONS {
+            let mut chess = Chess::from_fen(fen).unwrap();
+            let start = chess.clone();
+            walk(&mut chess, 2, &mut |chess| {
+                let mut rebuilt = chess.clone();
+                rebuilt.refresh_bitboards();
+                assert_eq!(chess.pieces, rebuilt.pieces, "{}", chess.to_fen());
+                assert_eq!(chess.white_pieces, rebuilt.white_pieces);
+                assert_eq!(chess.black_pieces, rebuilt.black_pieces);
+            });
+            assert_eq!(chess, start);
+        }
+    }
 }
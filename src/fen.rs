@@ -0,0 +1,493 @@
+use crate::chess::{Chess, Piece};
+use crate::notation::{parse_square, square_name};
+use std::fmt;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenError {
+    MissingField(&'static str),
+    TooManyFields(usize),
+    WrongRankCount(usize),
+    BadRankLength(usize),
+    InvalidPiece(char),
+    PawnOnBackRank(String),
+    KingCount { white: usize, black: usize },
+    AdjacentKings,
+    OpponentInCheck,
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    CastlingPiecesMissing(char),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {} field", field),
+            FenError::TooManyFields(count) => {
+                write!(f, "expected at most 6 fields, found {}", count)
+            }
+            FenError::WrongRankCount(count) => {
+                write!(f, "expected 8 ranks in piece placement, found {}", count)
+            }
+            FenError::BadRankLength(rank) => {
+                write!(f, "rank {} does not describe exactly 8 squares", rank)
+            }
+            FenError::InvalidPiece(c) => write!(f, "invalid piece character '{}'", c),
+            FenError::PawnOnBackRank(square) => write!(f, "pawn on the back rank at {}", square),
+            FenError::KingCount { white, black } => write!(
+                f,
+                "expected one king per side, found {} white and {} black",
+                white, black
+            ),
+            FenError::AdjacentKings => write!(f, "the kings stand next to each other"),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::CastlingPiecesMissing(c) => write!(
+                f,
+                "castling right '{}' without the king and rook on their home squares",
+                c
+            ),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl Chess {
+    pub fn from_fen(fen: &str) -> Result<Chess, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() > 6 {
+            return Err(FenError::TooManyFields(fields.len()));
+        }
+        let placement = fields
+            .first()
+            .ok_or(FenError::MissingField("piece placement"))?;
+        let side = fields
+            .get(1)
+            .ok_or(FenError::MissingField("side to move"))?;
+        let castling = fields.get(2).ok_or(FenError::MissingField("castling"))?;
+        let en_passant = fields.get(3).ok_or(FenError::MissingField("en passant"))?;
+
+        let mut chess = Chess::new();
+        chess.board = Chess::parse_placement(placement)?;
+        chess.refresh_bitboards();
+        let (white_kings, black_kings) = Chess::find_kings(&chess.board);
+        if white_kings.len() != 1 || black_kings.len() != 1 {
+            return Err(FenError::KingCount {
+                white: white_kings.len(),
+                black: black_kings.len(),
+            });
+        }
+        chess.white_king = white_kings[0];
+        chess.black_king = black_kings[0];
+        if (chess.white_king / 8).abs_diff(chess.black_king / 8) <= 1
+            && (chess.white_king % 8).abs_diff(chess.black_king % 8) <= 1
+        {
+            return Err(FenError::AdjacentKings);
+        }
+
+        chess.is_white_turn = match *side {
+            "w" => true,
+            "b" => false,
+            _ => return Err(FenError::InvalidSideToMove(side.to_string())),
+        };
+
+        chess.castling = [false; 4];
+        if *castling != "-" {
+            for c in castling.chars() {
+                //the right, and the squares its king and rook have to be on
+                let (right, king, rook) = match c {
+                    'K' => (0, (60, Piece::Wking), (63, Piece::Wrook)),
+                    'Q' => (1, (60, Piece::Wking), (56, Piece::Wrook)),
+                    'k' => (2, (4, Piece::Bking), (7, Piece::Brook)),
+                    'q' => (3, (4, Piece::Bking), (0, Piece::Brook)),
+                    _ => return Err(FenError::InvalidCastling(castling.to_string())),
+                };
+                if chess.castling[right] {
+                    return Err(FenError::InvalidCastling(castling.to_string()));
+                }
+                if chess.board[king.0] != king.1 || chess.board[rook.0] != rook.1 {
+                    return Err(FenError::CastlingPiecesMissing(c));
+                }
+                chess.castling[right] = true;
+            }
+        }
+
+        chess.en_passant = if *en_passant == "-" {
+            64
+        } else {
+            let square = parse_square(en_passant)
+                .filter(|square| chess.en_passant_is_possible(*square))
+                .ok_or_else(|| FenError::InvalidEnPassant(en_passant.to_string()))?;
+            //like move_piece, only keep a square some pawn can actually capture on
+            let (pushed, capturer) = if chess.is_white_turn {
+                (square + 8, Piece::Wpawn)
+            } else {
+                (square - 8, Piece::Bpawn)
+            };
+            if chess.has_adjacent(pushed, capturer) {
+                square
+            } else {
+                64
+            }
+        };
+
+        chess.halfmove_clock = match fields.get(4) {
+            Some(clock) => clock
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(clock.to_string()))?,
+            None => 0,
+        };
+        chess.fullmove_number = match fields.get(5) {
+            Some(number) => match number.parse() {
+                Ok(n) if n >= 1 => n,
+                _ => return Err(FenError::InvalidFullmoveNumber(number.to_string())),
+            },
+            None => 1,
+        };
+
+        chess.refresh_attacks();
+        let (king, attacks) = if chess.is_white_turn {
+            (chess.black_king, chess.white_attack)
+        } else {
+            (chess.white_king, chess.black_attack)
+        };
+        if attacks.get_bit(king) {
+            return Err(FenError::OpponentInCheck);
+        }
+        chess.hash = chess.compute_hash();
+        Ok(chess)
+    }
+    fn parse_placement(placement: &str) -> Result<[Piece; 64], FenError> {
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        let mut board = [Piece::Empty; 64];
+        for (row, rank) in ranks.iter().enumerate() {
+            let mut col = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    if !(1..=8).contains(&skip) {
+                        return Err(FenError::InvalidPiece(c));
+                    }
+                    col += skip as usize;
+                } else {
+                    let piece = Piece::from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                    if col < 8 {
+                        board[row * 8 + col] = piece;
+                    }
+                    col += 1;
+                }
+                if col > 8 {
+                    return Err(FenError::BadRankLength(8 - row));
+                }
+            }
+            if col != 8 {
+                return Err(FenError::BadRankLength(8 - row));
+            }
+        }
+        //pawns never stand on their own back rank, and promote on reaching the other one
+        for square in (0..8).chain(56..64) {
+            if matches!(board[square], Piece::Wpawn | Piece::Bpawn) {
+                return Err(FenError::PawnOnBackRank(square_name(square as u8)));
+            }
+        }
+        Ok(board)
+    }
+    //the target square sits behind a pawn that just moved two squares, so that pawn stands
+    //in front of it and both squares it crossed are empty
+    fn en_passant_is_possible(&self, square: u8) -> bool {
+        let (row, pawn) = if self.is_white_turn {
+            (2, Piece::Bpawn)
+        } else {
+            (5, Piece::Wpawn)
+        };
+        if square / 8 != row {
+            return false;
+        }
+        let (pushed, start) = if self.is_white_turn {
+            (square + 8, square - 8)
+        } else {
+            (square - 8, square + 8)
+        };
+        self.board[pushed as usize] == pawn
+            && self.board[square as usize] == Piece::Empty
+            && self.board[start as usize] == Piece::Empty
+    }
+    fn find_kings(board: &[Piece; 64]) -> (Vec<u8>, Vec<u8>) {
+        let mut white = vec![];
+        let mut black = vec![];
+        for (i, piece) in board.iter().enumerate() {
+            match piece {
+                Piece::Wking => white.push(i as u8),
+                Piece::Bking => black.push(i as u8),
+                _ => (),
+            }
+        }
+        (white, black)
+    }
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for row in 0..8 {
+            let mut empty = 0;
+            for col in 0..8 {
+                let piece = self.board[row * 8 + col];
+                if piece == Piece::Empty {
+                    empty += 1;
+                } else {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(piece.to_char());
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if row < 7 {
+                fen.push('/');
+            }
+        }
+        fen.push_str(if self.is_white_turn { " w " } else { " b " });
+        let mut castling = String::new();
+        for (right, c) in self.castling.iter().zip(['K', 'Q', 'k', 'q']) {
+            if *right {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+        fen.push(' ');
+        if self.en_passant < 64 {
+            fen.push_str(&square_name(self.en_passant));
+        } else {
+            fen.push('-');
+        }
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(fen: &str) -> FenError {
+        Chess::from_fen(fen).unwrap_err()
+    }
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            START_FEN,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1",
+            "8/8/4k3/8/8/3K3R/8/8 w - - 99 80",
+        ] {
+            assert_eq!(Chess::from_fen(fen).expect(fen).to_fen(), fen);
+        }
+        //the clocks may be left out
+        assert_eq!(
+            Chess::from_fen("4k3/8/8/8/8/8/8/4K3 b - -")
+                .unwrap()
+                .to_fen(),
+            "4k3/8/8/8/8/8/8/4K3 b - - 0 1"
+        );
+        //an en passant square no pawn can capture on is dropped, as it is after 1. e4 e5
+        let mut played = Chess::new();
+        played.move_piece(52, 36, None);
+        played.move_piece(12, 28, None);
+        let chess =
+            Chess::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2")
+                .unwrap();
+        assert_eq!(chess.en_passant, 64);
+        assert_eq!(chess.hash, played.hash);
+        assert_eq!(chess.to_fen(), played.to_fen());
+    }
+
+    #[test]
+    fn missing_field() {
+        assert_eq!(error(""), FenError::MissingField("piece placement"));
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w"),
+            FenError::MissingField("castling")
+        );
+    }
+
+    #[test]
+    fn too_many_fields() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - 0 1 extra"),
+            FenError::TooManyFields(7)
+        );
+    }
+
+    #[test]
+    fn wrong_rank_count() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/4K3 w - - 0 1"),
+            FenError::WrongRankCount(7)
+        );
+    }
+
+    #[test]
+    fn bad_rank_length() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/7/4K3 w - - 0 1"),
+            FenError::BadRankLength(2)
+        );
+        assert_eq!(
+            error("4k4/8/8/8/8/8/8/4K3 w - - 0 1"),
+            FenError::BadRankLength(8)
+        );
+    }
+
+    #[test]
+    fn invalid_piece() {
+        assert_eq!(
+            error("4k3/8/8/8/3x4/8/8/4K3 w - - 0 1"),
+            FenError::InvalidPiece('x')
+        );
+        assert_eq!(
+            error("4k3/8/8/8/9/8/8/4K3 w - - 0 1"),
+            FenError::InvalidPiece('9')
+        );
+    }
+
+    #[test]
+    fn pawn_on_back_rank() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/p3K3 b - - 0 1"),
+            FenError::PawnOnBackRank("a1".to_string())
+        );
+        assert_eq!(
+            error("4kP2/8/8/8/8/8/8/4K3 w - - 0 1"),
+            FenError::PawnOnBackRank("f8".to_string())
+        );
+    }
+
+    #[test]
+    fn king_count() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/8 w - - 0 1"),
+            FenError::KingCount { white: 0, black: 1 }
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/K3K3 w - - 0 1"),
+            FenError::KingCount { white: 2, black: 1 }
+        );
+    }
+
+    #[test]
+    fn adjacent_kings() {
+        assert_eq!(
+            error("8/8/8/3kK3/8/8/8/8 w - - 0 1"),
+            FenError::AdjacentKings
+        );
+    }
+
+    #[test]
+    fn opponent_in_check() {
+        //black is in check from the rook with white to move
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4RK2 w - - 0 1"),
+            FenError::OpponentInCheck
+        );
+        assert!(Chess::from_fen("4k3/8/8/8/8/8/8/4RK2 b - - 0 1").is_ok());
+    }
+
+    #[test]
+    fn invalid_side_to_move() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 x - - 0 1"),
+            FenError::InvalidSideToMove("x".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_castling() {
+        assert_eq!(
+            error("r3k2r/8/8/8/8/8/8/R3K2R w KX - 0 1"),
+            FenError::InvalidCastling("KX".to_string())
+        );
+        assert_eq!(
+            error("r3k2r/8/8/8/8/8/8/R3K2R w KK - 0 1"),
+            FenError::InvalidCastling("KK".to_string())
+        );
+    }
+
+    #[test]
+    fn castling_pieces_missing() {
+        //no rook on h1
+        assert_eq!(
+            error("r3k2r/8/8/8/8/8/8/R3K3 w KQkq - 0 1"),
+            FenError::CastlingPiecesMissing('K')
+        );
+        //the black king has left e8
+        assert_eq!(
+            error("r2k3r/8/8/8/8/8/8/R3K2R w KQq - 0 1"),
+            FenError::CastlingPiecesMissing('q')
+        );
+    }
+
+    #[test]
+    fn invalid_en_passant() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - e9 0 1"),
+            FenError::InvalidEnPassant("e9".to_string())
+        );
+        //white to move takes en passant on the sixth rank, not the third
+        assert_eq!(
+            error("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1"),
+            FenError::InvalidEnPassant("e3".to_string())
+        );
+        //no black pawn on e5 that could have just moved past e6
+        assert_eq!(
+            error("4k3/8/8/3P4/8/8/8/4K3 w - e6 0 1"),
+            FenError::InvalidEnPassant("e6".to_string())
+        );
+        //the pawn could not have crossed an occupied square
+        assert_eq!(
+            error("4k3/4n3/8/3Pp3/8/8/8/4K3 w - e6 0 1"),
+            FenError::InvalidEnPassant("e6".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_halfmove_clock() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+            FenError::InvalidHalfmoveClock("x".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_fullmove_number() {
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - 0 one"),
+            FenError::InvalidFullmoveNumber("one".to_string())
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - 0 0"),
+            FenError::InvalidFullmoveNumber("0".to_string())
+        );
+    }
+}
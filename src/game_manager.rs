@@ -1,28 +1,62 @@
-use crate::ai::AI;
-use crate::bitboard::Bitboard;
-use crate::chess::{Chess, Piece};
 use crate::BlackWhite;
+use chess_ai::ai::mate_in;
+use chess_ai::pgn::{self, PgnGame, PgnMove};
+use chess_ai::{
+    Bitboard, Chess, ChessMove, GameStatus, Move, MoveList, Piece, SearchResult, Side, TimeBudget,
+    AI,
+};
 use macroquad::prelude::*;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 pub struct GameManager {
     pub ai: AI,
     pub chess: Chess,
     mouse_pos: Option<usize>,
+    legal_moves: MoveList, //of the selected piece
     textures: [Texture2D; 13],
     pos: (f32, f32),
     timer: Timer,
-    game_state: i32,
+    game_state: GameStatus,
     player_vs_ai: BlackWhite,
+    draw_claimed: bool,
+    history: Vec<ChessMove>,
+    pgn: PgnGame,
+    //the AI's last search and its principal variation in SAN, shown next to the board
+    last_search: Option<(SearchResult, String)>,
 }
 
 impl GameManager {
-    pub fn new(start: f32, add: f32, ai_depth: Option<i32>, player_vs_ai: BlackWhite) -> Self {
-        let mut g = GameManager {
-            ai: AI::new(ai_depth.unwrap_or(1), player_vs_ai == BlackWhite::White),
-            chess: Chess::new(),
+    pub fn new(
+        chess: Chess,
+        start: f32,
+        add: f32,
+        ai_depth: Option<i32>,
+        player_vs_ai: BlackWhite,
+    ) -> Self {
+        let mut pgn = PgnGame::new(&chess);
+        pgn.set_tag("Event", "Casual game");
+        pgn.set_tag("Site", "chess_ai");
+        pgn.set_tag("Date", pgn::today());
+        pgn.set_tag("Round", "-");
+        let ai_name = format!("chess_ai (depth {})", ai_depth.unwrap_or(1));
+        let (white, black) = match player_vs_ai {
+            BlackWhite::White => ("Player".to_string(), ai_name),
+            BlackWhite::Black => (ai_name, "Player".to_string()),
+            BlackWhite::Random => ("Player 1".to_string(), "Player 2".to_string()),
+        };
+        pgn.set_tag("White", white);
+        pgn.set_tag("Black", black);
+        //seconds for the game plus seconds added per move
+        pgn.set_tag(
+            "TimeControl",
+            format!("{}+{}", start.round() as u32, add.round() as u32),
+        );
+        GameManager {
+            ai: AI::new(ai_depth.unwrap_or(1)),
+            chess,
             mouse_pos: None,
+            legal_moves: MoveList::new(),
             textures: [
                 Texture2D::from_file_with_format(include_bytes!(r".\images\board.png"), None),
                 Texture2D::from_file_with_format(include_bytes!(r".\images\white_king.png"), None),
@@ -52,16 +86,15 @@ impl GameManager {
             ],
             pos: (100.0, 200.0),
             timer: Timer::new(start, add),
-            game_state: -2,
+            game_state: GameStatus::Ongoing { side: Side::White },
             player_vs_ai,
-        };
-        if player_vs_ai == BlackWhite::White {
-            g.timer.time_black = 0.0;
-        } else if player_vs_ai == BlackWhite::Black {
-            g.timer.time_white = 0.0;
+            draw_claimed: false,
+            history: vec![],
+            pgn,
+            last_search: None,
         }
-        g
     }
+    #[allow(dead_code)]
     fn draw_bitboard(&self, bitboard: Bitboard) {
         for row in 0..8 {
             for col in 0..8 {
@@ -75,8 +108,9 @@ impl GameManager {
             }
         }
     }
-    fn draw_move(&self, from: u8, to: u8) {
-        if (0..64).contains(&from) && (0..64).contains(&to) {
+    fn draw_move(&self, last_move: Move) {
+        if last_move != Move::NONE {
+            let (from, to) = (last_move.from(), last_move.to());
             let from_x = (from % 8) as f32 * 100.0 + self.pos.0;
             let from_y = (from / 8) as f32 * 100.0 + self.pos.1;
             draw_rectangle(from_x, from_y, 100.0, 100.0, YELLOW);
@@ -85,6 +119,7 @@ impl GameManager {
             draw_rectangle(to_x, to_y, 100.0, 100.0, YELLOW);
         }
     }
+    #[allow(dead_code)]
     fn draw_check(&self) {
         if self.chess.is_check(self.chess.king_loc()) {
             let x = (self.chess.king_loc() % 8) as f32 * 100.0 + self.pos.0;
@@ -92,31 +127,36 @@ impl GameManager {
             draw_rectangle(x, y, 100.0, 100.0, RED);
         }
     }
+    fn piece_texture(&self, piece: Piece) -> Option<Texture2D> {
+        match piece {
+            Piece::Wking => Some(self.textures[1]),
+            Piece::Wqueen => Some(self.textures[2]),
+            Piece::Wrook => Some(self.textures[3]),
+            Piece::Wbishop => Some(self.textures[4]),
+            Piece::Wknight => Some(self.textures[5]),
+            Piece::Wpawn => Some(self.textures[6]),
+            Piece::Bking => Some(self.textures[7]),
+            Piece::Bqueen => Some(self.textures[8]),
+            Piece::Brook => Some(self.textures[9]),
+            Piece::Bbishop => Some(self.textures[10]),
+            Piece::Bknight => Some(self.textures[11]),
+            Piece::Bpawn => Some(self.textures[12]),
+            Piece::Empty => None,
+        }
+    }
     pub fn draw(&self) {
         //switch to move later
         draw_texture(self.textures[0], self.pos.0, self.pos.1, WHITE);
         //self.draw_bitboard(self.chess.white_pins);
-        self.draw_move(self.chess.last_move.0, self.chess.last_move.1);
+        self.draw_move(self.chess.last_move);
         //self.draw_check();
         for (i, piece) in self.chess.board.iter().enumerate() {
             let row = i / 8;
             let col = i % 8;
             let x = col as f32 * 100.0 + self.pos.0;
             let y = row as f32 * 100.0 + self.pos.1;
-            match piece {
-                Piece::Wking => draw_texture(self.textures[1], x, y, WHITE),
-                Piece::Wqueen => draw_texture(self.textures[2], x, y, WHITE),
-                Piece::Wrook => draw_texture(self.textures[3], x, y, WHITE),
-                Piece::Wbishop => draw_texture(self.textures[4], x, y, WHITE),
-                Piece::Wknight => draw_texture(self.textures[5], x, y, WHITE),
-                Piece::Wpawn => draw_texture(self.textures[6], x, y, WHITE),
-                Piece::Bking => draw_texture(self.textures[7], x, y, WHITE),
-                Piece::Bqueen => draw_texture(self.textures[8], x, y, WHITE),
-                Piece::Brook => draw_texture(self.textures[9], x, y, WHITE),
-                Piece::Bbishop => draw_texture(self.textures[10], x, y, WHITE),
-                Piece::Bknight => draw_texture(self.textures[11], x, y, WHITE),
-                Piece::Bpawn => draw_texture(self.textures[12], x, y, WHITE),
-                Piece::Empty => (),
+            if let Some(texture) = self.piece_texture(*piece) {
+                draw_texture(texture, x, y, WHITE);
             }
         }
         let window_width = screen_width();
@@ -179,10 +219,40 @@ impl GameManager {
             200.0,
             BLACK,
         );
+        draw_text(
+            &self.chess.to_fen(),
+            self.pos.0,
+            self.pos.1 + 840.0,
+            30.0,
+            LIGHTGRAY,
+        );
         self.draw_title();
+        self.draw_search();
+    }
+    //depth, score and expected line of the AI's last move
+    fn draw_search(&self) {
+        let Some((result, line)) = &self.last_search else {
+            return;
+        };
+        let score = match mate_in(result.score) {
+            Some(moves) if moves > 0 => format!("mate in {}", moves),
+            Some(moves) => format!("mated in {}", -moves),
+            None => format!("{:+.2}", result.score as f32 / 100.0),
+        };
+        let info = format!(
+            "AI: depth {}/{}  {}  {} nodes",
+            result.depth, result.seldepth, score, result.nodes
+        );
+        draw_text(&info, 1070.0, 500.0, 30.0, LIGHTGRAY);
+        draw_text(line, 1070.0, 540.0, 30.0, LIGHTGRAY);
     }
     pub fn draw_moves(&self) {
-        for i in self.chess.moves.clone() {
+        for (n, legal_move) in self.legal_moves.iter().enumerate() {
+            //the four promotions to a square follow each other, one marker is enough
+            if n > 0 && self.legal_moves[n - 1].to() == legal_move.to() {
+                continue;
+            }
+            let i = legal_move.to();
             if self.chess.board[i as usize] == Piece::Empty {
                 draw_circle(
                     50.0 + (i as i32 % 8 * 100) as f32 + self.pos.0,
@@ -227,7 +297,7 @@ impl GameManager {
         }
     }
     pub async fn player_turn(&mut self) {
-        self.chess.moves = vec![];
+        self.legal_moves = MoveList::new();
         loop {
             self.draw();
             self.draw_moves();
@@ -238,28 +308,34 @@ impl GameManager {
                     && self.chess.board[x].is_white() == self.chess.is_white_turn
                 {
                     let piece_index = x;
-                    self.chess.get_legals(piece_index);
+                    self.legal_moves = self.chess.get_legals(piece_index);
                     while !is_mouse_button_pressed(MouseButton::Left) {
                         self.draw();
                         self.draw_moves();
                         next_frame().await;
                     }
                     self.get_mouse_pos();
-                    if self.mouse_pos.is_some()
-                        && self
-                            .chess
-                            .moves
-                            .contains(&(self.mouse_pos.expect("liam is obese") as u8))
-                    {
-                        self.chess.move_piece(
-                            piece_index as u8,
-                            self.mouse_pos.expect("liam is obese") as u8,
-                        );
-                        self.chess.moves = vec![];
-                        break;
+                    if let Some(to) = self.mouse_pos {
+                        let targets = self.legal_moves.iter().filter(|m| m.to() == to as u8);
+                        let targets: Vec<Move> = targets.copied().collect();
+                        if !targets.is_empty() {
+                            self.legal_moves = MoveList::new();
+                            let promotion = if self.chess.is_promotion(piece_index as u8, to as u8)
+                            {
+                                Some(self.choose_promotion().await)
+                            } else {
+                                None
+                            };
+                            if let Some(&legal_move) =
+                                targets.iter().find(|m| m.promotion() == promotion)
+                            {
+                                self.record_move(legal_move);
+                            }
+                            break;
+                        }
                     }
                 } else {
-                    self.chess.moves = vec![];
+                    self.legal_moves = MoveList::new();
                     while !is_mouse_button_pressed(MouseButton::Left) {
                         self.draw();
                         self.draw_moves();
@@ -282,35 +358,92 @@ impl GameManager {
             self.timer.update_white();
         }
     }
-    pub fn ai_turn(&mut self) {
-        let (from, to) = self.ai.best_move(&mut self.chess);
-        self.chess.move_piece(from, to);
-    }
-    pub fn game_state(&mut self) -> i32 {
-        //firstly handle timers
-        match self.chess.is_ending() {
-            2 => {
-                if self.chess.is_white_turn {
-                    self.game_state = -2;
-                } else {
-                    self.game_state = -3;
+    //shows the four promotion pieces next to the board and waits for the player to click one
+    async fn choose_promotion(&mut self) -> Piece {
+        let choices = self.chess.promotion_pieces();
+        let x = self.pos.0 + 810.0;
+        let y = self.pos.1 + 200.0;
+        loop {
+            self.draw();
+            draw_rectangle(x, y, 100.0, 400.0, LIGHTGRAY);
+            for (i, &piece) in choices.iter().enumerate() {
+                if let Some(texture) = self.piece_texture(piece) {
+                    draw_texture(texture, x, y + i as f32 * 100.0, WHITE);
                 }
-                return 2;
             }
-            x => self.game_state = x,
-        };
+            if is_mouse_button_pressed(MouseButton::Left) {
+                let (mouse_x, mouse_y) = mouse_position();
+                if mouse_x > x && mouse_x < x + 100.0 && mouse_y > y && mouse_y < y + 400.0 {
+                    return choices[((mouse_y - y) / 100.0) as usize];
+                }
+            }
+            next_frame().await;
+        }
+    }
+    //thinks for a share of the AI side's clock, which is then charged like a player's
+    pub fn ai_turn(&mut self) {
+        let remaining = self.timer.remaining(self.chess.side_to_move()).max(0.0);
+        let budget = TimeBudget::from_clock(
+            Duration::from_secs_f32(remaining),
+            Duration::from_secs_f32(self.timer.add),
+            None,
+        );
+        let result = self.ai.think(&mut self.chess, Some(budget), |_| ());
+        //only the first few moves fit next to the board
+        let line = self.chess.line_to_san(&result.pv[..result.pv.len().min(8)]);
+        self.record_move(result.best_move);
+        self.last_search = Some((result, line));
+        if self.chess.is_white_turn {
+            self.timer.update_black();
+        } else {
+            self.timer.update_white();
+        }
+    }
+    //plays the move and keeps it for the PGN of the game
+    fn record_move(&mut self, legal_move: Move) {
+        let san = self.chess.move_to_san(legal_move);
+        self.pgn.moves.push(PgnMove::new(san, legal_move));
+        self.history.push(self.chess.make_move(legal_move));
+    }
+    //writes the finished game to game_<unix time>.pgn in the working directory
+    fn save_pgn(&mut self) {
+        self.pgn.set_tag("Result", pgn::result(self.game_state));
+        self.pgn
+            .set_tag("Termination", pgn::termination(self.game_state));
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        let path = format!("game_{}.pgn", seconds);
+        if let Err(error) = std::fs::write(&path, self.pgn.to_pgn()) {
+            eprintln!("could not save {}: {}", path, error);
+        }
+    }
+    pub fn game_state(&mut self) -> GameStatus {
+        if !self.game_state.is_over() {
+            self.game_state = self.chess.is_ending();
+        }
         self.game_state
     }
+    //the player to move may claim a draw under the fifty move rule
+    fn claim_draw(&mut self) {
+        if is_key_pressed(KeyCode::D) && self.chess.can_claim_fifty_move() {
+            self.draw_claimed = true;
+            self.game_state = GameStatus::FiftyMove;
+        }
+    }
     pub async fn pvp(&mut self) {
         clear_background(BLACK);
-        while self.game_state() == 2 {
+        while !self.game_state().is_over() {
             self.draw();
+            self.claim_draw();
             if is_mouse_button_pressed(MouseButton::Left) {
                 self.get_mouse_pos();
                 self.player_turn().await;
             }
             next_frame().await;
         }
+        self.save_pgn();
         loop {
             self.draw();
             next_frame().await;
@@ -318,99 +451,158 @@ impl GameManager {
     }
     pub async fn pvai(&mut self) {
         clear_background(BLACK);
-        if self.player_vs_ai == BlackWhite::Black {
-            let ai_start_time = Instant::now();
+        //the position may start with either side to move
+        if self.chess.is_white_turn == (self.player_vs_ai == BlackWhite::Black) {
             self.ai_turn();
-            let ai_duration = ai_start_time.elapsed();
-            //println!("AI's turn duration: {:?}", ai_duration);
         }
-        while self.game_state() == 2 {
+        while !self.game_state().is_over() {
             self.draw();
+            self.claim_draw();
             if is_mouse_button_pressed(MouseButton::Left) {
                 self.get_mouse_pos();
                 self.player_turn().await;
                 self.draw();
                 next_frame().await;
 
-                let ai_start_time = Instant::now();
-                self.ai_turn();
-                let ai_duration = ai_start_time.elapsed();
-                //println!("AI's turn duration: {:?}", ai_duration);
+                if !self.game_state().is_over() {
+                    self.ai_turn();
+                }
+            }
+            next_frame().await;
+        }
+        self.save_pgn();
+        loop {
+            self.draw();
+            next_frame().await;
+        }
+    }
+    //steps through the main line of a loaded game with the arrow keys
+    pub async fn replay(&mut self, game: &PgnGame) {
+        clear_background(BLACK);
+        loop {
+            if is_key_pressed(KeyCode::Right) {
+                if let Some(pgn_move) = game.moves.get(self.history.len()) {
+                    self.history.push(self.chess.make_move(pgn_move.legal_move));
+                }
+            } else if is_key_pressed(KeyCode::Left) {
+                if let Some(chess_move) = self.history.pop() {
+                    self.chess.undo_move(chess_move);
+                }
             }
+            self.game_state = self.chess.is_ending();
+            self.draw();
+            self.draw_replay_text(game);
             next_frame().await;
         }
-        // loop {
-        //     self.draw();
-        //     next_frame().await;
-        // }
+    }
+    fn draw_replay_text(&self, game: &PgnGame) {
+        let players = format!(
+            "{} - {}  {}",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?"),
+            game.tag("Result").unwrap_or("*")
+        );
+        draw_text(&players, 1070.0, 160.0, 50.0, GREEN);
+        let ply = self.history.len();
+        let text = match ply.checked_sub(1).map(|i| &game.moves[i]) {
+            //black just moved, and the fullmove number has already gone up
+            Some(last) if self.chess.is_white_turn => {
+                format!("{}... {}", self.chess.fullmove_number - 1, last.san)
+            }
+            Some(last) => format!("{}. {}", self.chess.fullmove_number, last.san),
+            None => "start".to_string(),
+        };
+        draw_text(
+            &format!("{} ({}/{})", text, ply, game.moves.len()),
+            1070.0,
+            420.0,
+            50.0,
+            GREEN,
+        );
+        if let Some(comment) = ply
+            .checked_sub(1)
+            .and_then(|i| game.moves[i].comment.as_ref())
+        {
+            draw_text(comment, 1070.0, 470.0, 30.0, LIGHTGRAY);
+        }
+        draw_text(
+            "left and right arrow keys step through the game",
+            1070.0,
+            1000.0,
+            30.0,
+            LIGHTGRAY,
+        );
     }
     fn winning_title(&self) {
         let text = match self.game_state {
-            0 => "tie by stalemate",
-            -1 => "black won by checkmate",
-            1 => "white won by checkmate",
-            3 => "tie by insufficient material",
-            4 => "tie by threefold repetition",
-            5 => "tie by the 50 move rule",
-            6 => "time forfeit",
-            _ => "undefined",
+            GameStatus::Stalemate => "tie by stalemate",
+            GameStatus::Checkmate {
+                winner: Side::Black,
+            } => "black won by checkmate",
+            GameStatus::Checkmate {
+                winner: Side::White,
+            } => "white won by checkmate",
+            GameStatus::InsufficientMaterial => "tie by insufficient material",
+            GameStatus::Repetition => "tie by threefold repetition",
+            GameStatus::FiftyMove if self.draw_claimed => "tie by the 50 move rule",
+            GameStatus::FiftyMove => "tie by the 75 move rule",
+            GameStatus::TimeForfeit {
+                winner: Side::Black,
+            } => "black won on time",
+            GameStatus::TimeForfeit {
+                winner: Side::White,
+            } => "white won on time",
+            GameStatus::Resignation {
+                winner: Side::Black,
+            } => "white resigned",
+            GameStatus::Resignation {
+                winner: Side::White,
+            } => "black resigned",
+            GameStatus::Agreement => "tie by agreement",
+            GameStatus::Ongoing { .. } => "undefined",
         };
         let text_width = measure_text(text, None, 100, 1.0).width;
         let screen_width = screen_width();
         let x_centered = (screen_width - text_width) / 2.0;
         draw_text(text, x_centered, 150.0, 100.0, GREEN);
     }
+    fn draw_crown(&self, side: Side) {
+        let loc = match side {
+            Side::White => self.chess.white_king,
+            Side::Black => self.chess.black_king,
+        };
+        let x = (loc % 8) as f32 * 100.0 + self.pos.0;
+        let y = (loc / 8) as f32 * 100.0 + self.pos.1 - 35.0;
+        draw_texture(
+            Texture2D::from_file_with_format(include_bytes!(r".\images\crown.png"), None),
+            x,
+            y,
+            WHITE,
+        );
+    }
     fn draw_title(&self) {
-        if self.game_state == -3 {
-            draw_text("black turn", 1070.0, 350.0, 100.0, GREEN);
-        } else if self.game_state == -2 {
-            draw_text("white turn", 1070.0, 350.0, 100.0, GREEN);
-        } else {
-            self.winning_title();
-        }
-        if self.game_state == -1 {
-            let loc = self.chess.black_king;
-            let x = (loc % 8) as f32 * 100.0 + self.pos.0;
-            let y = (loc / 8) as f32 * 100.0 + self.pos.1 - 35.0;
-            draw_texture(
-                Texture2D::from_file_with_format(include_bytes!(r".\images\crown.png"), None),
-                x,
-                y,
-                WHITE,
-            );
-        }
-        if self.game_state == 1 {
-            let loc = self.chess.white_king;
-            let x = (loc % 8) as f32 * 100.0 + self.pos.0;
-            let y = (loc / 8) as f32 * 100.0 + self.pos.1 - 35.0;
-            draw_texture(
-                Texture2D::from_file_with_format(include_bytes!(r".\images\crown.png"), None),
-                x,
-                y,
-                WHITE,
-            );
-        }
-        if self.game_state == 6 {} // winner titles and crowns for time forfiet
-        if self.game_state == 0 || (3..6).contains(&self.game_state) {
-            //draw title
-            let loc = self.chess.white_king;
-            let x = (loc % 8) as f32 * 100.0 + self.pos.0;
-            let y = (loc / 8) as f32 * 100.0 + self.pos.1 - 35.0;
-            draw_texture(
-                Texture2D::from_file_with_format(include_bytes!(r".\images\crown.png"), None),
-                x,
-                y,
-                WHITE,
-            );
-            let loc = self.chess.black_king;
-            let x = (loc % 8) as f32 * 100.0 + self.pos.0;
-            let y = (loc / 8) as f32 * 100.0 + self.pos.1 - 35.0;
-            draw_texture(
-                Texture2D::from_file_with_format(include_bytes!(r".\images\crown.png"), None),
-                x,
-                y,
-                WHITE,
-            );
+        match self.game_state {
+            GameStatus::Ongoing { side } => {
+                if side == Side::Black {
+                    draw_text("black turn", 1070.0, 350.0, 100.0, GREEN);
+                } else {
+                    draw_text("white turn", 1070.0, 350.0, 100.0, GREEN);
+                }
+                if self.chess.can_claim_fifty_move() {
+                    draw_text("press D to claim a draw", 1070.0, 420.0, 50.0, GREEN);
+                }
+            }
+            status => {
+                self.winning_title();
+                match status.winner() {
+                    Some(winner) => self.draw_crown(winner),
+                    None => {
+                        //draw title
+                        self.draw_crown(Side::White);
+                        self.draw_crown(Side::Black);
+                    }
+                }
+            }
         }
     }
 }
@@ -430,13 +622,21 @@ impl Timer {
             add,
         }
     }
+    //seconds left for the side to move, counting the time since the last move
+    fn remaining(&self, side: Side) -> f32 {
+        let time = match side {
+            Side::White => self.time_white,
+            Side::Black => self.time_black,
+        };
+        time - self.start_time.elapsed().as_secs_f32()
+    }
     fn update_white(&mut self) {
-        self.time_white -= self.start_time.elapsed().as_secs() as f32;
+        self.time_white -= self.start_time.elapsed().as_secs_f32();
         self.time_white += self.add;
         self.reset();
     }
     fn update_black(&mut self) {
-        self.time_black -= self.start_time.elapsed().as_secs() as f32;
+        self.time_black -= self.start_time.elapsed().as_secs_f32();
         self.time_black += self.add;
         self.reset();
     }
@@ -0,0 +1,18 @@
+pub mod ai;
+pub mod bitboard;
+pub mod chess;
+pub mod fen;
+pub mod magic;
+pub mod moves;
+pub mod notation;
+pub mod ordering;
+pub mod perft;
+pub mod pgn;
+pub mod tt;
+pub mod zobrist;
+
+pub use ai::{SearchResult, TimeBudget, AI};
+pub use bitboard::Bitboard;
+pub use chess::{Chess, ChessMove, GameStatus, Piece, Side};
+pub use moves::{Move, MoveList};
+pub use tt::TranspositionTable;
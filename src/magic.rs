@@ -0,0 +1,362 @@
+use crate::bitboard::Bitboard;
+use std::sync::LazyLock;
+
+//sliding attacks by lookup: the blockers on a square's rays, multiplied by a magic number,
+//give a collision free index into a table of precomputed attack sets
+struct Magic {
+    mask: u64, //the squares whose blockers matter, leaving out the board edge
+    magic: u64,
+    shift: u32,
+    offset: usize, //start of the square's slice in Tables::attacks
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied.0 & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct Tables {
+    rook: Vec<Magic>,
+    bishop: Vec<Magic>,
+    attacks: Vec<Bitboard>,
+}
+
+//built on first use
+static TABLES: LazyLock<Tables> = LazyLock::new(Tables::new);
+
+//found by find_magic from the seed in Tables::new, stored so startup doesn't have to search
+const ROOK_MAGICS: [u64; 64] = [
+    0x0980008011400020,
+    0x8340004410002000,
+    0x0880200090008268,
+    0x0080080080100004,
+    0x8100110004020800,
+    0x0300010004000822,
+    0x08801A0029000080,
+    0x8100050001204882,
+    0x0844800081400320,
+    0x0804402010004000,
+    0x0108802003100480,
+    0x0004808008001000,
+    0x0003001801001014,
+    0x0002000200041008,
+    0x0004008108042210,
+    0x0105000100009042,
+    0x0400808000400021,
+    0xC100404010002000,
+    0x0060008010002088,
+    0x0400808008001000,
+    0x4440808008000400,
+    0x1002008004000280,
+    0x40024400300D1248,
+    0x0010020000408104,
+    0x0101008200204200,
+    0x8020002040005000,
+    0x4100100080802000,
+    0x4008006A80100280,
+    0x1020080080040080,
+    0x0004010040020040,
+    0x0018A12400080290,
+    0x6140004200008104,
+    0x4000400020800090,
+    0x2020002080804000,
+    0x0000408202002010,
+    0x0080100501000820,
+    0x0000800400800800,
+    0x000A200408014010,
+    0x0100800200800100,
+    0xA00800570200008C,
+    0x008000406000C010,
+    0x1040100028002000,
+    0x0048200100110040,
+    0x0068490210030020,
+    0x1009080005010010,
+    0x2142000804010100,
+    0x1001080110840002,
+    0x1801004400820001,
+    0x010440208D020200,
+    0x0000400020008080,
+    0x0200200080100280,
+    0x0000100020090100,
+    0x0204008008020480,
+    0x8104010040020040,
+    0x78000201B0080400,
+    0x0040800051002880,
+    0x0050108001002041,
+    0x208A801100614003,
+    0x0006002042089082,
+    0x0011090004201001,
+    0x1002001004200802,
+    0x0005000208040001,
+    0x0002002701AC0822,
+    0x000010250184004A,
+];
+const BISHOP_MAGICS: [u64; 64] = [
+    0xC0A0012206040EA0,
+    0x8010228200420001,
+    0x0110008220400400,
+    0x02445C0080106000,
+    0x0044042004008100,
+    0x0880900420408C05,
+    0x0201080110080002,
+    0x0000108094202000,
+    0x0000042002040108,
+    0x0000623024110042,
+    0x0086100094811002,
+    0x0000044502002080,
+    0x0100460211400040,
+    0x0008109004200004,
+    0x0202320084844000,
+    0x8040042421041009,
+    0x201010C05102008C,
+    0x1020888208024080,
+    0x0108000C80290200,
+    0x8048000420425203,
+    0x0005000090402000,
+    0x2080400201104100,
+    0x8820420111101000,
+    0x4AC0302208821802,
+    0x000440001002A840,
+    0x2002200010041080,
+    0x1012080201004400,
+    0x8440040002410120,
+    0x1090820084010400,
+    0x2084852012021000,
+    0x12040062C1011003,
+    0x02008205E1090080,
+    0x088C102808042080,
+    0x0802102200904280,
+    0x8020209002080020,
+    0x2200080800060A00,
+    0x20C0004010010100,
+    0x0802004100821003,
+    0x0008024400008080,
+    0x0000840102008090,
+    0x0030A40420244007,
+    0x0A19084210011282,
+    0x0004082090019806,
+    0x6108004208020080,
+    0x0081200410110100,
+    0x1040810701010208,
+    0x0282047832012080,
+    0x0010020099000020,
+    0x000E010422400840,
+    0x10204208B0089090,
+    0x081004440C048000,
+    0x88C0180084040001,
+    0x3100020803040080,
+    0x890070A041210C00,
+    0x0020200101010A09,
+    0x0004100240410400,
+    0x0006004402080200,
+    0x0801062484042000,
+    0x00010002D7441004,
+    0x0810080000208800,
+    0x0000020808030411,
+    0x1450001020014440,
+    0x004060081081A288,
+    0x0044011404108A00,
+];
+
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+pub fn rook_attacks(square: u8, occupied: Bitboard) -> Bitboard {
+    let tables = &*TABLES;
+    tables.attacks[tables.rook[square as usize].index(occupied)]
+}
+
+pub fn bishop_attacks(square: u8, occupied: Bitboard) -> Bitboard {
+    let tables = &*TABLES;
+    tables.attacks[tables.bishop[square as usize].index(occupied)]
+}
+
+//builds the tables now instead of during the first search
+pub fn init() {
+    LazyLock::force(&TABLES);
+}
+
+//walks every ray until it leaves the board or hits a blocker, which is included
+fn slide(square: u8, occupied: u64, directions: &[(i8, i8); 4]) -> u64 {
+    let mut attacks = 0;
+    for &(row_step, col_step) in directions {
+        let (mut row, mut col) = ((square / 8) as i8, (square % 8) as i8);
+        loop {
+            row += row_step;
+            col += col_step;
+            if !(0..8).contains(&row) || !(0..8).contains(&col) {
+                break;
+            }
+            let bit = 1 << (row * 8 + col);
+            attacks |= bit;
+            if occupied & bit != 0 {
+                break;
+            }
+        }
+    }
+    attacks
+}
+
+//a blocker on the last square of a ray can't hide anything, so the edges are left out
+fn relevant_mask(square: u8, directions: &[(i8, i8); 4]) -> u64 {
+    let mut mask = 0;
+    for &(row_step, col_step) in directions {
+        let (mut row, mut col) = ((square / 8) as i8, (square % 8) as i8);
+        loop {
+            row += row_step;
+            col += col_step;
+            let (next_row, next_col) = (row + row_step, col + col_step);
+            if !(0..8).contains(&next_row) || !(0..8).contains(&next_col) {
+                break;
+            }
+            mask |= 1 << (row * 8 + col);
+        }
+    }
+    mask
+}
+
+//xorshift with a fixed seed, so every run finds the same magics
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    //magics with few bits set are found much sooner
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
+
+impl Tables {
+    fn new() -> Self {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let mut attacks = vec![];
+        let rook = (0..64)
+            .map(|square| {
+                let known = ROOK_MAGICS[square as usize];
+                find_magic(square, &ROOK_DIRECTIONS, known, &mut attacks, &mut rng)
+            })
+            .collect();
+        let bishop = (0..64)
+            .map(|square| {
+                let known = BISHOP_MAGICS[square as usize];
+                find_magic(square, &BISHOP_DIRECTIONS, known, &mut attacks, &mut rng)
+            })
+            .collect();
+        Tables {
+            rook,
+            bishop,
+            attacks,
+        }
+    }
+}
+
+//tries known and then random numbers until every blocker subset of the mask lands on a slot with the right attacks
+fn find_magic(
+    square: u8,
+    directions: &[(i8, i8); 4],
+    known: u64,
+    attacks: &mut Vec<Bitboard>,
+    rng: &mut Rng,
+) -> Magic {
+    let mask = relevant_mask(square, directions);
+    let bits = mask.count_ones();
+    let size = 1 << bits;
+    //every subset of the mask, enumerated with the carry-rippler trick
+    let mut blockers = Vec::with_capacity(size);
+    let mut reference = Vec::with_capacity(size);
+    let mut subset: u64 = 0;
+    loop {
+        blockers.push(subset);
+        reference.push(slide(square, subset, directions));
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+    let offset = attacks.len();
+    attacks.resize(offset + size, Bitboard::empty());
+    //which attempt last wrote each slot, so the table doesn't need clearing between attempts
+    let mut written = vec![0u32; size];
+    let mut attempt = 0;
+    loop {
+        let magic = if attempt == 0 { known } else { rng.sparse() };
+        //a good magic moves enough mask bits into the top byte
+        if attempt > 0 && (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+        attempt += 1;
+        let candidate = Magic {
+            mask,
+            magic,
+            shift: 64 - bits,
+            offset,
+        };
+        let fits = blockers
+            .iter()
+            .zip(&reference)
+            .all(|(&blocker, &expected)| {
+                let slot = candidate.index(Bitboard(blocker));
+                if written[slot - offset] != attempt {
+                    written[slot - offset] = attempt;
+                    attacks[slot] = Bitboard(expected);
+                    true
+                } else {
+                    attacks[slot].0 == expected
+                }
+            });
+        if fits {
+            return candidate;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_match_ray_walking() {
+        let mut rng = Rng(7);
+        for square in 0..64 {
+            for _ in 0..200 {
+                let occupied = rng.sparse();
+                assert_eq!(
+                    rook_attacks(square, Bitboard(occupied)).0,
+                    slide(square, occupied, &ROOK_DIRECTIONS)
+                );
+                assert_eq!(
+                    bishop_attacks(square, Bitboard(occupied)).0,
+                    slide(square, occupied, &BISHOP_DIRECTIONS)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stored_magics_fit() {
+        let tables = &*TABLES;
+        for square in 0..64 {
+            assert_eq!(tables.rook[square].magic, ROOK_MAGICS[square]);
+            assert_eq!(tables.bishop[square].magic, BISHOP_MAGICS[square]);
+        }
+    }
+
+    #[test]
+    fn blocked_rays() {
+        //rook on a8 with blockers on c8 and a6
+        let mut occupied = Bitboard::empty();
+        occupied.set_bit(2);
+        occupied.set_bit(16);
+        let attacks: Vec<u8> = rook_attacks(0, occupied).squares().collect();
+        assert_eq!(attacks, [1, 2, 8, 16]);
+        //bishop on e4 on an empty board sees 13 squares
+        assert_eq!(bishop_attacks(36, Bitboard::empty()).popcount(), 13);
+        assert_eq!(rook_attacks(36, Bitboard::empty()).popcount(), 14);
+    }
+}
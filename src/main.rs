@@ -1,8 +1,5 @@
-mod ai;
-mod bitboard;
-mod chess;
 mod game_manager;
-use crate::{ai::AI, chess::Chess};
+use chess_ai::{fen, magic, pgn, Chess};
 use game_manager::GameManager;
 use macroquad::{
     prelude::{
@@ -10,11 +7,9 @@ use macroquad::{
         mouse_position, next_frame, screen_height, screen_width, Conf, MouseButton, Texture2D,
         BLACK, DARKGRAY, GREEN, WHITE,
     },
-    time::get_fps,
     window::clear_background,
 };
 use rand::Rng;
-use std::time::Instant;
 
 pub fn window_conf() -> Conf {
     Conf {
@@ -30,6 +25,7 @@ pub fn window_conf() -> Conf {
 #[macroquad::main(window_conf)]
 async fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
+    magic::init();
     menu().await;
 }
 
@@ -78,10 +74,10 @@ async fn main() {
 //     println!("undo_move elapsed time: {:?}", elapsed_time_undo_move);
 // }
 
-// fn test_move_generation_speed(depth_ai: i32) {
+// fn test_move_generation_speed(depth_ai: u32) {
 //     let start_time = Instant::now();
 //     let mut chess = Chess::new();
-//     let moves = AI::count_moves(depth_ai, &mut chess);
+//     let moves = perft::perft(&mut chess, depth_ai);
 //     let duration = start_time.elapsed();
 //     println!("Generated {} moves in {:?}", moves, duration);
 // }
@@ -91,7 +87,11 @@ async fn menu() {
     let mut blackwhite = BlackWhite::Random;
     let mut time: f32 = 300.0;
     let mut additional_time_per_move: f32 = 2.0;
-    let mut depth_ai = 4;
+    let mut depth_ai = 12;
+    let mut fen = fen::START_FEN.to_owned();
+    let mut fen_error: Option<String> = None;
+    let mut pgn_path = String::new();
+    let mut pgn_error: Option<String> = None;
     let button_width = 400.0;
     let button_height = 200.0;
     let button_pos = egui::Pos2::new(
@@ -129,7 +129,26 @@ async fn menu() {
                     ui.spacing_mut().item_spacing.x = 10.0;
                     ui.radio_value(&mut pv, Pv::Pvai, "Player vs AI");
                     ui.radio_value(&mut pv, Pv::Pvp, "Player vs Player");
+                    ui.radio_value(&mut pv, Pv::Replay, "Replay PGN");
                 });
+                if pv == Pv::Replay {
+                    ui.separator();
+                    ui.heading(
+                        egui::RichText::new("Game to Replay")
+                            .heading()
+                            .color(egui::Color32::GREEN),
+                    );
+                    ui.label(
+                        egui::RichText::new("PGN file:")
+                            .heading()
+                            .color(egui::Color32::LIGHT_BLUE),
+                    );
+                    ui.text_edit_singleline(&mut pgn_path);
+                    if let Some(error) = &pgn_error {
+                        ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                    }
+                    return;
+                }
                 if pv == Pv::Pvai {
                     ui.separator();
                     ui.heading(
@@ -184,16 +203,31 @@ async fn menu() {
                             .color(egui::Color32::GREEN),
                     );
                     ui.label(
-                        egui::RichText::new("Depth (AI):")
+                        egui::RichText::new("Max depth (AI):")
                             .heading()
                             .color(egui::Color32::LIGHT_BLUE),
                     );
                     ui.add(
-                        egui::Slider::new(&mut depth_ai, 2..=4)
+                        egui::Slider::new(&mut depth_ai, 2..=20)
                             .text("")
                             .clamp_to_range(true),
                     );
                 }
+                ui.separator();
+                ui.heading(
+                    egui::RichText::new("Starting Position")
+                        .heading()
+                        .color(egui::Color32::GREEN),
+                );
+                ui.label(
+                    egui::RichText::new("FEN:")
+                        .heading()
+                        .color(egui::Color32::LIGHT_BLUE),
+                );
+                ui.text_edit_singleline(&mut fen);
+                if let Some(error) = &fen_error {
+                    ui.label(egui::RichText::new(error).color(egui::Color32::RED));
+                }
             });
         });
         draw_text("CHESS", 700.0, 150.0, 200.0, GREEN);
@@ -219,9 +253,50 @@ async fn menu() {
             && mouse_position().1 >= button_pos.y
             && mouse_position().1 <= button_pos.y + button_height
         {
+            if pv == Pv::Replay {
+                //the first game in the file
+                let game = std::fs::read_to_string(&pgn_path)
+                    .map_err(|error| error.to_string())
+                    .and_then(|text| pgn::parse_games(&text).map_err(|error| error.to_string()))
+                    .and_then(|games| {
+                        games
+                            .into_iter()
+                            .next()
+                            .ok_or_else(|| "no games in file".to_string())
+                    });
+                match game {
+                    Ok(game) => {
+                        let chess = game.start().unwrap_or_default();
+                        let mut replay =
+                            GameManager::new(chess, 0.0, 0.0, None, BlackWhite::Random);
+                        replay.replay(&game).await;
+                        return;
+                    }
+                    Err(error) => {
+                        pgn_error = Some(format!("could not load PGN: {}", error));
+                        egui_macroquad::draw();
+                        next_frame().await;
+                        continue;
+                    }
+                }
+            }
+            let chess = match Chess::from_fen(&fen) {
+                Ok(chess) => chess,
+                Err(error) => {
+                    fen_error = Some(format!("invalid FEN: {}", error));
+                    egui_macroquad::draw();
+                    next_frame().await;
+                    continue;
+                }
+            };
             if pv == Pv::Pvp {
-                let mut game =
-                    GameManager::new(time, additional_time_per_move, None, BlackWhite::Random);
+                let mut game = GameManager::new(
+                    chess,
+                    time,
+                    additional_time_per_move,
+                    None,
+                    BlackWhite::Random,
+                );
                 game.pvp().await;
                 return;
             } else {
@@ -233,8 +308,13 @@ async fn menu() {
                         BlackWhite::Black
                     };
                 }
-                let mut game =
-                    GameManager::new(time, additional_time_per_move, Some(depth_ai), blackwhite);
+                let mut game = GameManager::new(
+                    chess,
+                    time,
+                    additional_time_per_move,
+                    Some(depth_ai),
+                    blackwhite,
+                );
                 game.pvai().await;
                 return;
             }
@@ -286,17 +366,13 @@ pub enum BlackWhite {
 enum Pv {
     Pvp,
     Pvai,
+    Replay,
 }
-//game endings: Draw by Insufficient Material, Draw by Threefold Repetition, Draw by Fifty-Move Rule, Time Forfeit (also make them useful in a game)
+//game endings: Time Forfeit (also make them useful in a game)
 
 //todo list:
-//undo promotions, attacks maps, pins
-//game endings- timers, threefold, insufficient
+//game endings- timers
 //better ai
-//magic bitboards?
 
 //bugs go here:
-//an passant didnt take pawn- prob does now idfk
-//horsey cant take checking piece? (pawns can)
-//bishop pin sucks ass
 //i hate my life
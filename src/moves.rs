@@ -0,0 +1,205 @@
+use crate::chess::Piece;
+use crate::notation::square_name;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+//from in bits 0-5, to in bits 6-11 and the kind of move in bits 12-15
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Move(u16);
+
+impl Move {
+    pub const QUIET: u16 = 0;
+    pub const DOUBLE_PUSH: u16 = 1;
+    pub const KING_CASTLE: u16 = 2;
+    pub const QUEEN_CASTLE: u16 = 3;
+    pub const CAPTURE: u16 = 4;
+    pub const EN_PASSANT: u16 = 5;
+    //the two low bits of a promotion pick knight, bishop, rook or queen
+    pub const PROMOTION: u16 = 8;
+    //a8a8, never a legal move
+    pub const NONE: Move = Move(0);
+
+    pub fn new(from: u8, to: u8, flags: u16) -> Self {
+        Move(from as u16 | (to as u16) << 6 | flags << 12)
+    }
+    //flags for a pawn promoting to piece, a capture as well if capture is set
+    pub fn promotion_flags(piece: Piece, capture: bool) -> u16 {
+        let kind = match piece {
+            Piece::Wknight | Piece::Bknight => 0,
+            Piece::Wbishop | Piece::Bbishop => 1,
+            Piece::Wrook | Piece::Brook => 2,
+            _ => 3,
+        };
+        Move::PROMOTION | if capture { Move::CAPTURE } else { 0 } | kind
+    }
+    pub fn from(self) -> u8 {
+        (self.0 & 63) as u8
+    }
+    pub fn to(self) -> u8 {
+        (self.0 >> 6 & 63) as u8
+    }
+    pub fn flags(self) -> u16 {
+        self.0 >> 12
+    }
+    pub fn is_capture(self) -> bool {
+        self.flags() & Move::CAPTURE != 0
+    }
+    pub fn is_castle(self) -> bool {
+        matches!(self.flags(), Move::KING_CASTLE | Move::QUEEN_CASTLE)
+    }
+    pub fn is_en_passant(self) -> bool {
+        self.flags() == Move::EN_PASSANT
+    }
+    //the piece the pawn turns into, white when it promotes on the eighth rank
+    pub fn promotion(self) -> Option<Piece> {
+        if self.flags() & Move::PROMOTION == 0 {
+            return None;
+        }
+        let white = self.to() < 8;
+        Some(match (self.flags() & 3, white) {
+            (0, true) => Piece::Wknight,
+            (0, false) => Piece::Bknight,
+            (1, true) => Piece::Wbishop,
+            (1, false) => Piece::Bbishop,
+            (2, true) => Piece::Wrook,
+            (2, false) => Piece::Brook,
+            (_, true) => Piece::Wqueen,
+            (_, false) => Piece::Bqueen,
+        })
+    }
+}
+
+//long algebraic as used by UCI: e2e4, e1g1 for castling, e7e8q for promotions
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", square_name(self.from()), square_name(self.to()))?;
+        if let Some(piece) = self.promotion() {
+            write!(f, "{}", piece.to_char().to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+//no position has more than 218 legal moves
+const MAX_MOVES: usize = 256;
+
+//a fixed size list on the stack, so generating moves doesn't allocate
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [Move::NONE; MAX_MOVES],
+            len: 0,
+        }
+    }
+    pub fn push(&mut self, legal_move: Move) {
+        self.moves[self.len] = legal_move;
+        self.len += 1;
+    }
+    //keeps the moves for which keep returns true, in the same order
+    pub fn retain(&mut self, mut keep: impl FnMut(Move) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+impl Deref for MoveList {
+    type Target = [Move];
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl fmt::Debug for MoveList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, MAX_MOVES>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packing() {
+        let quiet = Move::new(52, 36, Move::DOUBLE_PUSH);
+        assert_eq!((quiet.from(), quiet.to()), (52, 36));
+        assert!(!quiet.is_capture());
+        assert_eq!(quiet.promotion(), None);
+        assert_eq!(quiet.to_string(), "e2e4");
+        assert_eq!(std::mem::size_of::<Move>(), 2);
+
+        let promotion = Move::new(9, 0, Move::promotion_flags(Piece::Wknight, true));
+        assert!(promotion.is_capture());
+        assert_eq!(promotion.promotion(), Some(Piece::Wknight));
+        assert_eq!(promotion.to_string(), "b7a8n");
+        let promotion = Move::new(54, 62, Move::promotion_flags(Piece::Bqueen, false));
+        assert!(!promotion.is_capture());
+        assert_eq!(promotion.promotion(), Some(Piece::Bqueen));
+
+        assert!(Move::new(60, 62, Move::KING_CASTLE).is_castle());
+        assert!(Move::new(28, 19, Move::EN_PASSANT).is_en_passant());
+        assert!(Move::new(28, 19, Move::EN_PASSANT).is_capture());
+    }
+
+    #[test]
+    fn lists() {
+        let mut moves = MoveList::new();
+        assert!(moves.is_empty());
+        moves.push(Move::new(52, 36, Move::DOUBLE_PUSH));
+        moves.push(Move::new(62, 45, Move::QUIET));
+        assert_eq!(moves.len(), 2);
+        moves.swap(0, 1);
+        assert_eq!(moves[0].to_string(), "g1f3");
+        moves.push(Move::new(51, 35, Move::DOUBLE_PUSH));
+        moves.retain(|m| m.flags() == Move::DOUBLE_PUSH);
+        assert_eq!(moves.len(), 2);
+        let names: Vec<String> = moves.into_iter().map(|m| m.to_string()).collect();
+        assert_eq!(names, ["e2e4", "d2d4"]);
+    }
+}
@@ -0,0 +1,342 @@
+use crate::chess::{Chess, Piece};
+use crate::moves::Move;
+
+//squares are indexed from a8 (0) to h1 (63)
+pub fn square_name(square: u8) -> String {
+    let file = (b'a' + square % 8) as char;
+    let rank = (b'8' - square / 8) as char;
+    format!("{}{}", file, rank)
+}
+
+pub fn parse_square(name: &str) -> Option<u8> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1])
+    {
+        return None;
+    }
+    Some((b'8' - bytes[1]) * 8 + (bytes[0] - b'a'))
+}
+
+impl Chess {
+    //the legal move written as text in UCI notation, None if it is malformed or illegal
+    pub fn parse_uci_move(&mut self, text: &str) -> Option<Move> {
+        if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+            return None;
+        }
+        let from = parse_square(&text[0..2])?;
+        let to = parse_square(&text[2..4])?;
+        //some GUIs send the promotion piece in upper case
+        let promotion = text[4..].chars().next().map(|c| c.to_ascii_lowercase());
+        self.get_all_moves().into_iter().find(|m| {
+            m.from() == from
+                && m.to() == to
+                && m.promotion()
+                    .map(|piece| piece.to_char().to_ascii_lowercase())
+                    == promotion
+        })
+    }
+    //standard algebraic notation of a legal move, written before the move is played
+    pub fn move_to_san(&mut self, legal_move: Move) -> String {
+        let (from, to) = (legal_move.from(), legal_move.to());
+        let piece = self.board[from as usize];
+        let is_pawn = matches!(piece, Piece::Wpawn | Piece::Bpawn);
+        let mut san = if legal_move.is_castle() {
+            if legal_move.flags() == Move::KING_CASTLE {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            }
+        } else {
+            let is_capture = legal_move.is_capture();
+            let mut san = String::new();
+            if is_pawn {
+                if is_capture {
+                    san.push((b'a' + from % 8) as char);
+                }
+            } else {
+                san.push(piece.to_char().to_ascii_uppercase());
+                san.push_str(&self.disambiguation(from, to));
+            }
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(to));
+            if let Some(promotion) = legal_move.promotion() {
+                san.push('=');
+                san.push(promotion.to_char().to_ascii_uppercase());
+            }
+            san
+        };
+        let chess_move = self.make_move(legal_move);
+        if self.is_check(self.king_loc()) {
+            san.push(if self.get_all_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+        self.undo_move(chess_move);
+        san
+    }
+    //the legal move written in SAN, None if it is malformed, illegal or ambiguous
+    pub fn parse_san(&mut self, text: &str) -> Option<Move> {
+        //check, mate and annotation suffixes don't change the move
+        let text = text.trim_end_matches(['+', '#', '!', '?']);
+        let text = text.strip_suffix("e.p.").unwrap_or(text);
+        let moves = self.get_all_moves();
+        let castle_file = match text {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
+        };
+        if let Some(file) = castle_file {
+            return moves
+                .into_iter()
+                .find(|m| m.is_castle() && m.to() % 8 == file);
+        }
+
+        let mut chars: Vec<char> = text.chars().filter(|&c| c != 'x' && c != '-').collect();
+        let kind = match chars.first() {
+            Some(&c) if "KQRBN".contains(c) => {
+                chars.remove(0);
+                c
+            }
+            _ => 'P',
+        };
+        //e8=Q, also accepted without the =
+        let promotion = match chars.last() {
+            Some(&c) if kind == 'P' && "QRBN".contains(c) => {
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+                Some(c)
+            }
+            _ => None,
+        };
+        if chars.len() < 2 {
+            return None;
+        }
+        let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = parse_square(&to)?;
+        let (mut file, mut rank) = (None, None);
+        for c in chars {
+            match c {
+                'a'..='h' if file.is_none() => file = Some(c as u8 - b'a'),
+                '1'..='8' if rank.is_none() => rank = Some(b'8' - c as u8),
+                _ => return None,
+            }
+        }
+
+        let mut candidates = moves.into_iter().filter(|m| {
+            let from = m.from();
+            m.to() == to
+                && self.board[from as usize].to_char().to_ascii_uppercase() == kind
+                && file.is_none_or(|file| from % 8 == file)
+                && rank.is_none_or(|rank| from / 8 == rank)
+                && m.promotion()
+                    .map(|piece| piece.to_char().to_ascii_uppercase())
+                    == promotion
+        });
+        let found = candidates.next()?;
+        match candidates.next() {
+            Some(_) => None,
+            None => Some(found),
+        }
+    }
+    //a sequence of legal moves from this position in SAN with move numbers, like 12... Nf6 13. e4
+    pub fn line_to_san(&mut self, line: &[Move]) -> String {
+        let mut text = vec![];
+        let mut played = vec![];
+        for (i, &legal_move) in line.iter().enumerate() {
+            if self.is_white_turn {
+                text.push(format!("{}.", self.fullmove_number));
+            } else if i == 0 {
+                text.push(format!("{}...", self.fullmove_number));
+            }
+            text.push(self.move_to_san(legal_move));
+            played.push(self.make_move(legal_move));
+        }
+        while let Some(chess_move) = played.pop() {
+            self.undo_move(chess_move);
+        }
+        text.join(" ")
+    }
+    //the file, rank or whole square of from, if another piece of the same kind can also reach to
+    fn disambiguation(&mut self, from: u8, to: u8) -> String {
+        let piece = self.board[from as usize];
+        let rivals: Vec<u8> = self
+            .get_all_moves()
+            .into_iter()
+            .map(|m| (m.from(), m.to()))
+            .filter(|&(f, t)| t == to && f != from && self.board[f as usize] == piece)
+            .map(|(f, _)| f)
+            .collect();
+        let name = square_name(from);
+        if rivals.is_empty() {
+            String::new()
+        } else if rivals.iter().all(|&rival| rival % 8 != from % 8) {
+            name[0..1].to_string()
+        } else if rivals.iter().all(|&rival| rival / 8 != from / 8) {
+            name[1..2].to_string()
+        } else {
+            name
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::perft::{walk, WALK_POSITIONS};
+
+    #[test]
+    fn square_names() {
+        assert_eq!(square_name(0), "a8");
+        assert_eq!(square_name(63), "h1");
+        assert_eq!(square_name(52), "e2");
+        for square in 0..64 {
+            assert_eq!(parse_square(&square_name(square)), Some(square));
+        }
+        assert_eq!(parse_square("i1"), None);
+        assert_eq!(parse_square("a9"), None);
+        assert_eq!(parse_square("e"), None);
+    }
+
+    #[test]
+    fn uci_moves() {
+        let mut chess = Chess::new();
+        assert_eq!(
+            chess.parse_uci_move("e2e4"),
+            Some(Move::new(52, 36, Move::DOUBLE_PUSH))
+        );
+        assert_eq!(chess.parse_uci_move("e2e5"), None);
+        assert_eq!(chess.parse_uci_move("e2e4q"), None);
+
+        let mut chess = Chess::from_fen("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            chess.parse_uci_move("e7e8n"),
+            Some(Move::new(
+                12,
+                4,
+                Move::promotion_flags(Piece::Wknight, false)
+            ))
+        );
+        assert_eq!(chess.parse_uci_move("e7e8"), None);
+        assert_eq!(chess.parse_uci_move("e7e8Q").unwrap().to_string(), "e7e8q");
+    }
+
+    fn san(fen: &str, uci: &str) -> String {
+        let mut chess = Chess::from_fen(fen).unwrap();
+        let chess_move = chess.parse_uci_move(uci).unwrap();
+        chess.move_to_san(chess_move)
+    }
+
+    #[test]
+    fn san_moves() {
+        let start = crate::fen::START_FEN;
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+        //knights on b1 and f3 can both reach d2, rooks on a1 and a5 both reach a3
+        let fen = "4k3/8/8/R7/8/5N2/8/RN2K3 w - - 0 1";
+        assert_eq!(san(fen, "b1d2"), "Nbd2");
+        assert_eq!(san(fen, "a1a3"), "R1a3");
+        assert_eq!(san(fen, "a5a3"), "R5a3");
+        assert_eq!(san("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), "exd5");
+        assert_eq!(san("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), "exd6");
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1"), "O-O");
+        assert_eq!(san("r3k3/8/8/8/8/8/8/3K4 b q - 0 1", "e8c8"), "O-O-O+");
+        assert_eq!(san("8/4P1k1/8/8/8/8/8/4K3 w - - 0 1", "e7e8q"), "e8=Q");
+        assert_eq!(san("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1", "f7g7"), "Qg7#");
+    }
+
+    #[test]
+    fn parse_san_moves() {
+        let mut chess = Chess::new();
+        assert_eq!(
+            chess.parse_san("e4"),
+            Some(Move::new(52, 36, Move::DOUBLE_PUSH))
+        );
+        assert_eq!(chess.parse_san("Nf3"), Some(Move::new(62, 45, Move::QUIET)));
+        assert_eq!(
+            chess.parse_san("Ng1f3"),
+            Some(Move::new(62, 45, Move::QUIET))
+        );
+        assert_eq!(chess.parse_san("e5"), None);
+        assert_eq!(chess.parse_san("Qd4"), None);
+        assert_eq!(chess.parse_san("O-O"), None);
+
+        let mut chess = Chess::from_fen("4k3/8/8/R7/8/5N2/8/RN2K3 w - - 0 1").unwrap();
+        assert_eq!(chess.parse_san("Nd2"), None);
+        assert_eq!(
+            chess.parse_san("Nbd2"),
+            Some(Move::new(57, 51, Move::QUIET))
+        );
+        assert_eq!(
+            chess.parse_san("R1a3+!?"),
+            Some(Move::new(56, 40, Move::QUIET))
+        );
+        assert_eq!(chess.parse_san("Ra3"), None);
+
+        let mut chess = Chess::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        assert_eq!(
+            chess.parse_san("exd6"),
+            Some(Move::new(28, 19, Move::EN_PASSANT))
+        );
+        assert_eq!(
+            chess.parse_san("exd6e.p."),
+            Some(Move::new(28, 19, Move::EN_PASSANT))
+        );
+        assert_eq!(
+            chess.parse_san("O-O"),
+            Some(Move::new(60, 62, Move::KING_CASTLE))
+        );
+        assert_eq!(
+            chess.parse_san("0-0-0"),
+            Some(Move::new(60, 58, Move::QUEEN_CASTLE))
+        );
+
+        let mut chess = Chess::from_fen("1n5k/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            chess.parse_san("a8=Q"),
+            Some(Move::new(8, 0, Move::promotion_flags(Piece::Wqueen, false)))
+        );
+        assert_eq!(
+            chess.parse_san("axb8N"),
+            Some(Move::new(8, 1, Move::promotion_flags(Piece::Wknight, true)))
+        );
+        assert_eq!(chess.parse_san("a8"), None);
+    }
+
+    #[test]
+    fn lines() {
+        let mut chess = Chess::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 12").unwrap();
+        let line = [
+            Move::new(4, 11, Move::QUIET),
+            Move::new(52, 36, Move::DOUBLE_PUSH),
+            Move::new(11, 20, Move::QUIET),
+        ];
+        assert_eq!(chess.line_to_san(&line), "12... Kd7 13. e4 Ke6");
+        assert_eq!(chess.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12");
+        let line = [
+            Move::new(62, 45, Move::QUIET),
+            Move::new(1, 18, Move::QUIET),
+        ];
+        assert_eq!(Chess::new().line_to_san(&line), "1. Nf3 Nc6");
+    }
+
+    #[test]
+    fn notation_round_trips() {
+        //every legal move two plies deep comes back unchanged from both notations
+        for fen in WALK_POSITIONS {
+            walk(&mut Chess::from_fen(fen).unwrap(), 1, &mut |chess| {
+                for legal_move in chess.get_all_moves() {
+                    let san = chess.move_to_san(legal_move);
+                    assert_eq!(chess.parse_san(&san), Some(legal_move), "{}", san);
+                    let uci = legal_move.to_string();
+                    assert_eq!(chess.parse_uci_move(&uci), Some(legal_move), "{}", uci);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::ai::MAX_PLY;
+use crate::chess::{Chess, Piece};
+use crate::moves::Move;
+
+//bands of move scores, highest first: the hash move, captures and queen promotions,
+//the two killers, quiet moves by history and last the underpromotions
+const HASH_MOVE: i32 = 1 << 30;
+const CAPTURE: i32 = 1 << 29;
+const KILLER: i32 = 1 << 28;
+const UNDERPROMOTION: i32 = -(1 << 28);
+//history scores are halved once one gets this big, so they stay below the killers
+const HISTORY_LIMIT: i32 = 1 << 20;
+
+//what the search learned about quiet moves: the last two that caused a cutoff on each ply,
+//and how often each piece moving to each square did so, weighted by depth
+#[derive(Debug, Clone)]
+pub struct MoveOrdering {
+    killers: [[Move; 2]; MAX_PLY as usize],
+    history: [[i32; 64]; 12],
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        MoveOrdering::new()
+    }
+}
+
+impl MoveOrdering {
+    pub fn new() -> Self {
+        MoveOrdering {
+            killers: [[Move::NONE; 2]; MAX_PLY as usize],
+            history: [[0; 64]; 12],
+        }
+    }
+    //killers belong to the old position, history is mostly still good
+    pub fn new_search(&mut self) {
+        self.killers = [[Move::NONE; 2]; MAX_PLY as usize];
+        self.age_history();
+    }
+    fn age_history(&mut self) {
+        for score in self.history.iter_mut().flatten() {
+            *score /= 2;
+        }
+    }
+    //most promising first, so alpha-beta finds the cutoffs early
+    pub fn sort(&self, chess: &Chess, moves: &mut [Move], hash_move: Move, ply: i32) {
+        moves.sort_unstable_by_key(|&legal_move| -self.score(chess, legal_move, hash_move, ply));
+    }
+    fn score(&self, chess: &Chess, legal_move: Move, hash_move: Move, ply: i32) -> i32 {
+        if legal_move == hash_move {
+            return HASH_MOVE;
+        }
+        let piece = chess.board[legal_move.from() as usize];
+        match legal_move.promotion() {
+            Some(Piece::Wqueen | Piece::Bqueen) => {}
+            Some(_) => return UNDERPROMOTION,
+            None if !legal_move.is_capture() => {
+                let killers = self.killers(ply);
+                return if killers[0] == legal_move {
+                    KILLER
+                } else if killers[1] == legal_move {
+                    KILLER - 1
+                } else {
+                    self.history[piece.index()][legal_move.to() as usize]
+                };
+            }
+            None => {}
+        }
+        CAPTURE + mvv_lva(chess, legal_move)
+    }
+    fn killers(&self, ply: i32) -> [Move; 2] {
+        self.killers
+            .get(ply as usize)
+            .copied()
+            .unwrap_or([Move::NONE; 2])
+    }
+    //a move caused a beta cutoff, call before it is played or after it is undone
+    pub fn cutoff(&mut self, chess: &Chess, legal_move: Move, depth: i32, ply: i32) {
+        //captures and promotions are ordered well enough by what they win
+        if legal_move.is_capture() || legal_move.promotion().is_some() {
+            return;
+        }
+        if let Some(killers) = self.killers.get_mut(ply as usize) {
+            if killers[0] != legal_move {
+                killers[1] = killers[0];
+                killers[0] = legal_move;
+            }
+        }
+        let piece = chess.board[legal_move.from() as usize];
+        let score = &mut self.history[piece.index()][legal_move.to() as usize];
+        *score += depth * depth;
+        if *score >= HISTORY_LIMIT {
+            self.age_history();
+        }
+    }
+}
+
+//most valuable victim first, and of those the least valuable attacker. quiet moves come
+//after every capture, cheapest piece first
+pub fn mvv_lva(chess: &Chess, legal_move: Move) -> i32 {
+    let victim = if legal_move.is_en_passant() {
+        Piece::Wpawn
+    } else {
+        chess.board[legal_move.to() as usize]
+    };
+    let promotion = legal_move
+        .promotion()
+        .map_or(0, |piece| piece.evaluate().abs());
+    let attacker = chess.board[legal_move.from() as usize];
+    (victim.evaluate().abs() + promotion) * 100 - attacker.evaluate().abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order() {
+        //exd5 is the only capture, the knight and king moves are quiet
+        let chess = Chess::from_fen("4k3/8/8/3q4/4P3/8/3P1PPN/4K3 w - - 0 1").unwrap();
+        let mut ordering = MoveOrdering::new();
+        let mut moves = chess.get_all_moves();
+        let quiet = Move::new(60, 61, Move::QUIET);
+        let killer = Move::new(55, 45, Move::QUIET);
+        let hash_move = Move::new(53, 45, Move::QUIET);
+        ordering.cutoff(&chess, killer, 3, 2);
+        for _ in 0..3 {
+            ordering.cutoff(&chess, quiet, 5, 7);
+        }
+        ordering.sort(&chess, &mut moves, hash_move, 2);
+        assert_eq!(moves[0], hash_move);
+        assert_eq!(moves[1], Move::new(36, 27, Move::CAPTURE));
+        assert_eq!(moves[2], killer);
+        assert_eq!(moves[3], quiet);
+        //killers are kept per ply, history is shared
+        ordering.sort(&chess, &mut moves, Move::NONE, 7);
+        assert_eq!(moves[0], Move::new(36, 27, Move::CAPTURE));
+        assert_eq!(moves[1], quiet);
+        ordering.new_search();
+        assert_eq!(ordering.killers(7), [Move::NONE; 2]);
+        assert_eq!(ordering.history[Piece::Wking.index()][61], 3 * 25 / 2);
+    }
+}
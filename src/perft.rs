@@ -0,0 +1,181 @@
+use crate::chess::Chess;
+
+pub fn perft(chess: &mut Chess, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = chess.get_all_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for legal_move in moves {
+        let chess_move = chess.make_move(legal_move);
+        nodes += perft(chess, depth - 1);
+        chess.undo_move(chess_move);
+    }
+    nodes
+}
+
+//node count below every root move, for finding which move a generator bug hides under
+pub fn divide(chess: &mut Chess, depth: u32) -> Vec<(String, u64)> {
+    let mut counts = vec![];
+    if depth == 0 {
+        return counts;
+    }
+    for legal_move in chess.get_all_moves() {
+        let chess_move = chess.make_move(legal_move);
+        counts.push((legal_move.to_string(), perft(chess, depth - 1)));
+        chess.undo_move(chess_move);
+    }
+    counts
+}
+
+pub fn print_divide(chess: &mut Chess, depth: u32) {
+    let counts = divide(chess, depth);
+    let total: u64 = counts.iter().map(|(_, nodes)| nodes).sum();
+    for (name, nodes) in &counts {
+        println!("{}: {}", name, nodes);
+    }
+    println!();
+    println!("Moves: {}", counts.len());
+    println!("Nodes: {}", total);
+}
+
+//positions the tests walk every line of: castling both ways, promotions, en passant and checks
+#[cfg(test)]
+pub const WALK_POSITIONS: [&str; 5] = [
+    crate::fen::START_FEN,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+];
+
+//calls check on every position up to depth plies deep, and leaves chess as it found it
+#[cfg(test)]
+pub fn walk(chess: &mut Chess, depth: u32, check: &mut impl FnMut(&mut Chess)) {
+    check(chess);
+    if depth == 0 {
+        return;
+    }
+    for legal_move in chess.get_all_moves() {
+        let chess_move = chess.make_move(legal_move);
+        walk(chess, depth - 1, check);
+        chess.undo_move(chess_move);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::START_FEN;
+
+    fn check(fen: &str, expected: &[u64]) {
+        let mut chess = Chess::from_fen(fen).unwrap();
+        for (depth, &nodes) in expected.iter().enumerate() {
+            assert_eq!(
+                perft(&mut chess, depth as u32 + 1),
+                nodes,
+                "perft({}) of {}",
+                depth + 1,
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn start_position() {
+        check(START_FEN, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        check(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn rook_endgame_en_passant() {
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+    }
+
+    #[test]
+    fn promotions_and_castling() {
+        check(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn promotion_with_discovered_check() {
+        check(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        );
+    }
+
+    #[test]
+    fn middlegame() {
+        check(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890],
+        );
+    }
+
+    #[test]
+    fn edge_cases() {
+        let positions: [(&str, u32, u64); 15] = [
+            // en passant would expose the king along the rank
+            ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", 6, 1134888),
+            ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", 6, 1015133),
+            // en passant capture gives check
+            ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", 6, 1440467),
+            // castling gives check
+            ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", 6, 661072),
+            ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", 6, 803711),
+            // castling rights lost by rook captures, castling through attacked squares
+            ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", 4, 1274206),
+            ("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", 4, 1720476),
+            // a rook captured on its corner and replaced there by the other rook
+            ("r3k2r/8/8/8/8/8/1B5r/R3K3 w Qkq - 0 1", 4, 425708),
+            // promotion out of check, discovered check
+            ("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", 6, 3821001),
+            ("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1", 5, 1004658),
+            // promotion and underpromotion giving check
+            ("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", 6, 217342),
+            ("8/P1k5/K7/8/8/8/8/8 w - - 0 1", 6, 92683),
+            // stalemate and checkmate
+            ("K1k5/8/P7/8/8/8/8/8 w - - 0 1", 6, 2217),
+            ("8/k1P5/8/1K6/8/8/8/8 w - - 0 1", 7, 567584),
+            ("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", 4, 23527),
+        ];
+        let mut failures = vec![];
+        for (fen, depth, nodes) in positions {
+            let mut chess = Chess::from_fen(fen).unwrap();
+            let found = perft(&mut chess, depth);
+            if found != nodes {
+                failures.push(format!(
+                    "perft({}) of {}: expected {}, found {}",
+                    depth, fen, nodes, found
+                ));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn divide_adds_up_to_perft() {
+        let mut chess = Chess::from_fen(START_FEN).unwrap();
+        let counts = divide(&mut chess, 3);
+        assert_eq!(counts.len(), 20);
+        assert!(counts.contains(&("e2e4".to_string(), 600)));
+        assert_eq!(counts.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+    }
+}
@@ -0,0 +1,691 @@
+use crate::chess::{Chess, GameStatus, Side};
+use crate::fen::{FenError, START_FEN};
+use crate::moves::Move;
+use std::fmt;
+use std::iter::Peekable;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::vec::IntoIter;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<PgnMove>, //the main line
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnMove {
+    pub san: String,
+    pub legal_move: Move,
+    pub nags: Vec<u8>,
+    pub comment_before: Option<String>,
+    pub comment: Option<String>,
+    pub variations: Vec<Vec<PgnMove>>, //alternatives to this move, played from the position before it
+}
+
+impl PgnMove {
+    pub fn new(san: String, legal_move: Move) -> Self {
+        PgnMove {
+            san,
+            legal_move,
+            nags: vec![],
+            comment_before: None,
+            comment: None,
+            variations: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PgnError {
+    InvalidTag(String),
+    UnterminatedComment,
+    UnbalancedVariation,
+    VariationWithoutMove,
+    InvalidFen(FenError),
+    IllegalMove {
+        number: u32,
+        white: bool,
+        san: String,
+    },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag pair [{}]", tag),
+            PgnError::UnterminatedComment => write!(f, "comment is missing its closing brace"),
+            PgnError::UnbalancedVariation => write!(f, "unbalanced parentheses in variations"),
+            PgnError::VariationWithoutMove => {
+                write!(f, "variation does not follow a move it could replace")
+            }
+            PgnError::InvalidFen(error) => write!(f, "invalid FEN tag: {}", error),
+            PgnError::IllegalMove {
+                number,
+                white: true,
+                san,
+            } => write!(f, "illegal move {}. {}", number, san),
+            PgnError::IllegalMove { number, san, .. } => {
+                write!(f, "illegal move {}... {}", number, san)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<FenError> for PgnError {
+    fn from(error: FenError) -> Self {
+        PgnError::InvalidFen(error)
+    }
+}
+
+impl PgnGame {
+    //the seven tag roster with unknown values, plus SetUp and FEN when start isn't the initial position
+    pub fn new(start: &Chess) -> Self {
+        let mut game = PgnGame {
+            tags: vec![],
+            moves: vec![],
+        };
+        for (name, value) in [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ] {
+            game.set_tag(name, value);
+        }
+        let fen = start.to_fen();
+        if fen != START_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", fen);
+        }
+        game
+    }
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+    //replaces the value of an existing tag, so the roster keeps its order
+    pub fn set_tag(&mut self, name: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value,
+            None => self.tags.push((name.to_string(), value)),
+        }
+    }
+    //the position before the first move
+    pub fn start(&self) -> Result<Chess, FenError> {
+        match self.tag("FEN") {
+            Some(fen) => Chess::from_fen(fen),
+            None => Ok(Chess::new()),
+        }
+    }
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        pgn.push('\n');
+
+        let start = self.start().unwrap_or_default();
+        let mut tokens = vec![];
+        write_moves(
+            &self.moves,
+            start.fullmove_number,
+            start.is_white_turn,
+            &mut tokens,
+        );
+        tokens.push(self.tag("Result").unwrap_or("*").to_string());
+
+        //export format keeps movetext lines under 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > 79 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+fn write_moves(
+    moves: &[PgnMove],
+    mut number: u32,
+    mut white_to_move: bool,
+    tokens: &mut Vec<String>,
+) {
+    //black's moves only get a number at the start of a line or after an interruption
+    let mut needs_number = true;
+    for pgn_move in moves {
+        if let Some(comment) = &pgn_move.comment_before {
+            write_comment(comment, tokens);
+            needs_number = true;
+        }
+        if white_to_move {
+            tokens.push(format!("{}.", number));
+        } else if needs_number {
+            tokens.push(format!("{}...", number));
+        }
+        tokens.push(pgn_move.san.clone());
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+        needs_number = false;
+        if let Some(comment) = &pgn_move.comment {
+            write_comment(comment, tokens);
+            needs_number = true;
+        }
+        for variation in &pgn_move.variations {
+            let mut inner = vec![];
+            write_moves(variation, number, white_to_move, &mut inner);
+            if let Some(first) = inner.first_mut() {
+                first.insert(0, '(');
+            }
+            if let Some(last) = inner.last_mut() {
+                last.push(')');
+            }
+            tokens.append(&mut inner);
+            needs_number = true;
+        }
+        if !white_to_move {
+            number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+}
+
+//one token per word so long comments wrap with the rest of the movetext
+fn write_comment(comment: &str, tokens: &mut Vec<String>) {
+    let mut words: Vec<String> = comment.split_whitespace().map(String::from).collect();
+    if words.is_empty() {
+        words.push(String::new());
+    }
+    words[0].insert(0, '{');
+    if let Some(last) = words.last_mut() {
+        last.push('}');
+    }
+    tokens.append(&mut words);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Open,
+    Close,
+    Nag(u8),
+    San(String),
+    Result(String),
+}
+
+//every game in the text, with each move checked for legality while it is replayed
+pub fn parse_games(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut tokens = tokenize(text)?.into_iter().peekable();
+    let mut games = vec![];
+    while tokens.peek().is_some() {
+        let mut game = PgnGame {
+            tags: vec![],
+            moves: vec![],
+        };
+        while let Some(Token::Tag(name, value)) = tokens.peek() {
+            game.set_tag(name, value.clone());
+            tokens.next();
+        }
+        let mut chess = game.start()?;
+        game.moves = parse_line(&mut tokens, &mut chess, false)?;
+        if let Some(Token::Result(result)) = tokens.peek() {
+            if game.tag("Result").is_none() {
+                game.set_tag("Result", result.clone());
+            }
+            tokens.next();
+        }
+        games.push(game);
+    }
+    Ok(games)
+}
+
+//moves up to the end of the game or of the variation, chess is left as it was found
+fn parse_line(
+    tokens: &mut Peekable<IntoIter<Token>>,
+    chess: &mut Chess,
+    in_variation: bool,
+) -> Result<Vec<PgnMove>, PgnError> {
+    let mut moves: Vec<PgnMove> = vec![];
+    let mut played = vec![];
+    let mut comment_before = None;
+    loop {
+        match tokens.peek().cloned() {
+            Some(Token::San(san)) => {
+                tokens.next();
+                let Some(legal_move) = chess.parse_san(&san) else {
+                    return Err(PgnError::IllegalMove {
+                        number: chess.fullmove_number,
+                        white: chess.is_white_turn,
+                        san,
+                    });
+                };
+                let mut pgn_move = PgnMove::new(chess.move_to_san(legal_move), legal_move);
+                pgn_move.comment_before = comment_before.take();
+                moves.push(pgn_move);
+                played.push(chess.make_move(legal_move));
+            }
+            Some(Token::Nag(nag)) => {
+                if let Some(last) = moves.last_mut() {
+                    last.nags.push(nag);
+                }
+                tokens.next();
+            }
+            Some(Token::Comment(comment)) => {
+                let slot = match moves.last_mut() {
+                    Some(last) if comment_before.is_none() => &mut last.comment,
+                    _ => &mut comment_before,
+                };
+                //consecutive comments are joined
+                *slot = Some(match slot.take() {
+                    Some(earlier) => format!("{} {}", earlier, comment),
+                    None => comment,
+                });
+                tokens.next();
+            }
+            Some(Token::Open) => {
+                tokens.next();
+                //the variation replaces the last move, so it starts from the position before it
+                let Some(last) = played.pop() else {
+                    return Err(PgnError::VariationWithoutMove);
+                };
+                chess.undo_move(last);
+                let variation = parse_line(tokens, chess, true)?;
+                if tokens.next() != Some(Token::Close) {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                let last = moves.last_mut().unwrap();
+                last.variations.push(variation);
+                played.push(chess.make_move(last.legal_move));
+            }
+            Some(Token::Close) if !in_variation => return Err(PgnError::UnbalancedVariation),
+            Some(Token::Tag(..)) | Some(Token::Result(_)) | None if in_variation => {
+                return Err(PgnError::UnbalancedVariation)
+            }
+            _ => break,
+        }
+    }
+    while let Some(chess_move) = played.pop() {
+        chess.undo_move(chess_move);
+    }
+    Ok(moves)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        let at_line_start = line_start;
+        line_start = c == '\n';
+        match c {
+            //escaped lines are for other programs
+            '%' if at_line_start => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line_start = true;
+                        break;
+                    }
+                }
+            }
+            '[' => tokens.push(read_tag(&mut chars)?),
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::UnterminatedComment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let mut comment = String::new();
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line_start = true;
+                        break;
+                    }
+                    comment.push(c);
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                    chars.next();
+                }
+                if let Ok(nag) = digits.parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut symbol = c.to_string();
+                while let Some(&c) = chars
+                    .peek()
+                    .filter(|c| !c.is_whitespace() && !"(){}[];$".contains(**c))
+                {
+                    symbol.push(c);
+                    chars.next();
+                }
+                read_symbol(&symbol, &mut tokens);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// [Name "value"], with the opening bracket already read
+fn read_tag(chars: &mut Peekable<std::str::Chars>) -> Result<Token, PgnError> {
+    let mut raw = String::new();
+    let mut in_value = false;
+    loop {
+        match chars.next() {
+            None => return Err(PgnError::InvalidTag(raw)),
+            Some(']') if !in_value => break,
+            Some('\\') if in_value => {
+                raw.push('\\');
+                raw.extend(chars.next());
+            }
+            Some(c) => {
+                if c == '"' {
+                    in_value = !in_value;
+                }
+                raw.push(c);
+            }
+        }
+    }
+    let invalid = || PgnError::InvalidTag(raw.clone());
+    let (name, value) = raw
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .ok_or_else(invalid)?;
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(invalid());
+    }
+    let mut unescaped = String::new();
+    let mut value = value.chars();
+    while let Some(c) = value.next() {
+        unescaped.extend(if c == '\\' { value.next() } else { Some(c) });
+    }
+    Ok(Token::Tag(name.to_string(), unescaped))
+}
+
+fn read_symbol(symbol: &str, tokens: &mut Vec<Token>) {
+    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&symbol) {
+        tokens.push(Token::Result(symbol.to_string()));
+        return;
+    }
+    //move numbers like 12. or 12... may be glued to the move
+    let digits = symbol.len()
+        - symbol
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .len();
+    let symbol = if digits > 0 && symbol[digits..].starts_with('.') {
+        symbol[digits..].trim_start_matches('.')
+    } else {
+        symbol
+    };
+    let san = symbol.trim_end_matches(['!', '?']);
+    if !san.is_empty() {
+        tokens.push(Token::San(san.to_string()));
+    }
+    let nag = match &symbol[san.len()..] {
+        "!" => 1,
+        "?" => 2,
+        "!!" => 3,
+        "??" => 4,
+        "!?" => 5,
+        "?!" => 6,
+        _ => return,
+    };
+    tokens.push(Token::Nag(nag));
+}
+
+//the value of the Result tag
+pub fn result(status: GameStatus) -> &'static str {
+    match status {
+        GameStatus::Ongoing { .. } => "*",
+        status => match status.winner() {
+            Some(Side::White) => "1-0",
+            Some(Side::Black) => "0-1",
+            None => "1/2-1/2",
+        },
+    }
+}
+
+//the value of the Termination tag
+pub fn termination(status: GameStatus) -> &'static str {
+    match status {
+        GameStatus::Ongoing { .. } => "unterminated",
+        GameStatus::TimeForfeit { .. } => "time forfeit",
+        _ => "normal",
+    }
+}
+
+//today's UTC date as YYYY.MM.DD
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    civil_date(seconds / 86400)
+}
+
+//days since 1970-01-01 to a date, counting in 400 year eras that start on March 1st
+fn civil_date(days_since_epoch: u64) -> String {
+    let days = days_since_epoch as i64 + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(chess: &mut Chess, game: &mut PgnGame, uci: &str) {
+        let legal_move = chess.parse_uci_move(uci).unwrap();
+        let san = chess.move_to_san(legal_move);
+        game.moves.push(PgnMove::new(san, legal_move));
+        chess.make_move(legal_move);
+    }
+
+    #[test]
+    fn export() {
+        let mut chess = Chess::new();
+        let mut game = PgnGame::new(&chess);
+        for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            play(&mut chess, &mut game, uci);
+        }
+        game.set_tag("Event", "Fool's \"mate\"");
+        game.set_tag("Result", result(chess.is_ending()));
+        game.set_tag("Termination", termination(chess.is_ending()));
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"Fool's \\\"mate\\\"\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n\
+             [White \"?\"]\n[Black \"?\"]\n[Result \"0-1\"]\n[Termination \"normal\"]\n\n\
+             1. f3 e5 2. g4 Qh4# 0-1\n"
+        );
+    }
+
+    #[test]
+    fn export_from_a_position() {
+        let mut chess = Chess::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 12").unwrap();
+        let mut game = PgnGame::new(&chess);
+        for uci in ["e8d7", "e2e4", "d7e6"] {
+            play(&mut chess, &mut game, uci);
+        }
+        assert_eq!(game.tag("SetUp"), Some("1"));
+        assert!(game.to_pgn().ends_with("\n\n12... Kd7 13. e4 Ke6 *\n"));
+    }
+
+    #[test]
+    fn long_games_wrap() {
+        let mut chess = Chess::new();
+        let mut game = PgnGame::new(&chess);
+        for _ in 0..40 {
+            for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                play(&mut chess, &mut game, uci);
+            }
+        }
+        let pgn = game.to_pgn();
+        let movetext = pgn.split("\n\n").nth(1).unwrap();
+        assert!(movetext.lines().count() > 1);
+        assert!(movetext.lines().all(|line| line.len() < 80));
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(civil_date(0), "1970.01.01");
+        assert_eq!(civil_date(11016), "2000.02.29");
+        assert_eq!(civil_date(11017), "2000.03.01");
+        assert_eq!(civil_date(19722), "2023.12.31");
+        assert_eq!(today().len(), 10);
+    }
+
+    const ANNOTATED: &str = r#"
+[Event "Annotated \"example\""]
+[Site "?"]
+[Result "1-0"]
+
+% this line is for other programs
+{Opening comment} 1. e4 e5 2. Nf3 $1 Nc6 {Most popular} (2... d6 {Philidor}
+(2... Nf6 3. Nxe5 d6 4. Nf3) 3. d4) 3.Bb5 a6!? ; the Morphy defence
+4. Ba4 Nf6 5. O-O 1-0
+"#;
+
+    #[test]
+    fn parse_annotated_game() {
+        let games = parse_games(ANNOTATED).unwrap();
+        assert_eq!(games.len(), 1);
+        let game = &games[0];
+        assert_eq!(game.tag("Event"), Some("Annotated \"example\""));
+        assert_eq!(game.tag("Result"), Some("1-0"));
+        let sans: Vec<&str> = game.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(
+            sans,
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O"]
+        );
+        assert_eq!(
+            game.moves[0].comment_before.as_deref(),
+            Some("Opening comment")
+        );
+        assert_eq!(game.moves[2].nags, [1]);
+        assert_eq!(game.moves[3].comment.as_deref(), Some("Most popular"));
+        assert_eq!(game.moves[5].nags, [5]);
+        assert_eq!(game.moves[5].comment.as_deref(), Some("the Morphy defence"));
+        assert_eq!(game.moves[8].legal_move.to_string(), "e1g1");
+
+        let philidor = &game.moves[3].variations[0];
+        assert_eq!(philidor.len(), 2);
+        assert_eq!(philidor[0].san, "d6");
+        assert_eq!(philidor[1].san, "d4");
+        let petrov = &philidor[0].variations[0];
+        let sans: Vec<&str> = petrov.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(sans, ["Nf6", "Nxe5", "d6", "Nf3"]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let game = &parse_games(ANNOTATED).unwrap()[0];
+        let pgn = game.to_pgn();
+        assert!(pgn
+            .replace('\n', " ")
+            .contains("$1 Nc6 {Most popular} (2... d6 {Philidor} (2... Nf6 3. Nxe5"));
+        assert_eq!(&parse_games(&pgn).unwrap()[0], game);
+    }
+
+    #[test]
+    fn several_games() {
+        let text = "[White \"a\"]\n\n1. e4 *\n\n[White \"b\"]\n\n1. d4 d5 1/2-1/2\n1. c4 0-1";
+        let games = parse_games(text).unwrap();
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[1].tag("White"), Some("b"));
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(games[2].tag("Result"), Some("0-1"));
+    }
+
+    #[test]
+    fn games_from_a_position() {
+        let text = "[SetUp \"1\"]\n[FEN \"7k/5Q2/6K1/8/8/8/8/8 w - - 0 40\"]\n\n40. Qg7# 1-0";
+        let game = &parse_games(text).unwrap()[0];
+        assert_eq!(game.moves[0].legal_move.to_string(), "f7g7");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_games("1. e4 e5 2. Ke3"),
+            Err(PgnError::IllegalMove {
+                number: 2,
+                white: true,
+                san: "Ke3".to_string()
+            })
+        );
+        assert_eq!(
+            parse_games("1. e4 e5 2. Nf3 Nc6 3... Nf6")
+                .unwrap_err()
+                .to_string(),
+            "illegal move 3. Nf6"
+        );
+        assert_eq!(
+            parse_games("1. e4 (1. d4 *"),
+            Err(PgnError::UnbalancedVariation)
+        );
+        assert_eq!(parse_games("1. e4 e5)"), Err(PgnError::UnbalancedVariation));
+        assert_eq!(
+            parse_games("(1. d4) 1. e4"),
+            Err(PgnError::VariationWithoutMove)
+        );
+        assert_eq!(
+            parse_games("1. e4 {never closed"),
+            Err(PgnError::UnterminatedComment)
+        );
+        assert!(matches!(
+            parse_games("[Event \"unterminated"),
+            Err(PgnError::InvalidTag(_))
+        ));
+        assert!(matches!(
+            parse_games("[FEN \"8/8 w - - 0 1\"] *"),
+            Err(PgnError::InvalidFen(_))
+        ));
+    }
+}
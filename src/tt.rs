@@ -0,0 +1,193 @@
+use crate::ai::{MATE, MAX_PLY};
+use crate::moves::Move;
+
+//how a stored score relates to the true score of the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower, //the search failed high, the score is at least this
+    Upper, //the search failed low, the score is at most this
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry {
+    key: u64, //the full zobrist key, to tell apart positions sharing a slot
+    pub best_move: Move,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+    pub age: u8, //the search that stored it
+}
+
+const EMPTY: Entry = Entry {
+    key: 0,
+    best_move: Move::NONE,
+    score: 0,
+    depth: -1,
+    bound: Bound::Upper,
+    age: 0,
+};
+
+//searched positions by zobrist key, in buckets of two: the first slot keeps the deepest
+//entry of the current search, the second takes whatever the first turned down
+#[derive(Debug, Clone)]
+pub struct TranspositionTable {
+    buckets: Vec<[Entry; 2]>,
+    age: u8,
+    pub probes: u64,
+    pub hits: u64,
+    pub stores: u64,
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let bucket_size = std::mem::size_of::<[Entry; 2]>();
+        let count = (megabytes.max(1) << 20) / bucket_size;
+        TranspositionTable {
+            buckets: vec![[EMPTY; 2]; count],
+            age: 0,
+            probes: 0,
+            hits: 0,
+            stores: 0,
+        }
+    }
+    pub fn clear(&mut self) {
+        self.buckets.fill([EMPTY; 2]);
+        self.age = 0;
+        self.probes = 0;
+        self.hits = 0;
+        self.stores = 0;
+    }
+    //entries from earlier searches become the first to be replaced
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+    fn bucket(&self, key: u64) -> usize {
+        //the high bits, the low ones pick nothing the multiply doesn't already mix in
+        ((key as u128 * self.buckets.len() as u128) >> 64) as usize
+    }
+    pub fn probe(&mut self, key: u64) -> Option<Entry> {
+        self.probes += 1;
+        let bucket = self.bucket(key);
+        let found = self.buckets[bucket]
+            .iter()
+            .find(|entry| entry.key == key && entry.depth >= 0)
+            .copied();
+        if found.is_some() {
+            self.hits += 1;
+        }
+        found
+    }
+    pub fn store(&mut self, key: u64, depth: i32, bound: Bound, score: i32, best_move: Move) {
+        self.stores += 1;
+        let age = self.age;
+        let bucket = self.bucket(key);
+        let [deepest, recent] = &mut self.buckets[bucket];
+        let entry = Entry {
+            key,
+            //a search that found no better move keeps the one it was told to try first
+            best_move: match best_move {
+                Move::NONE if deepest.key == key => deepest.best_move,
+                Move::NONE if recent.key == key => recent.best_move,
+                _ => best_move,
+            },
+            score,
+            depth,
+            bound,
+            age,
+        };
+        if deepest.key == key || deepest.age != age || depth >= deepest.depth {
+            if deepest.key != key && deepest.age == age {
+                *recent = *deepest;
+            }
+            *deepest = entry;
+        } else {
+            *recent = entry;
+        }
+    }
+    //per mille of a sample of slots used in the current search, as UCI hashfull wants
+    pub fn hashfull(&self) -> u32 {
+        let sample = &self.buckets[..self.buckets.len().min(500)];
+        let used = sample
+            .iter()
+            .flatten()
+            .filter(|entry| entry.depth >= 0 && entry.age == self.age)
+            .count();
+        (used * 1000 / (sample.len() * 2).max(1)) as u32
+    }
+}
+
+//mate scores count plies from the root, the table stores them counted from the position itself
+pub fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE - MAX_PLY {
+        score + ply
+    } else if score <= -MATE + MAX_PLY {
+        score - ply
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE - MAX_PLY {
+        score - ply
+    } else if score <= -MATE + MAX_PLY {
+        score + ply
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        let best_move = Move::new(52, 36, Move::DOUBLE_PUSH);
+        assert_eq!(tt.probe(42), None);
+        tt.store(42, 3, Bound::Exact, 25, best_move);
+        let entry = tt.probe(42).unwrap();
+        assert_eq!(
+            (entry.depth, entry.bound, entry.score),
+            (3, Bound::Exact, 25)
+        );
+        assert_eq!(entry.best_move, best_move);
+        //no move of its own keeps the stored one for ordering
+        tt.store(42, 4, Bound::Upper, -10, Move::NONE);
+        assert_eq!(tt.probe(42).unwrap().best_move, best_move);
+        assert_eq!((tt.probes, tt.hits, tt.stores), (3, 2, 2));
+        tt.clear();
+        assert_eq!(tt.probe(42), None);
+    }
+
+    #[test]
+    fn replacement() {
+        let mut tt = TranspositionTable::new(1);
+        //keys that land in the same bucket
+        let keys = [1, 2, 3];
+        tt.store(keys[0], 8, Bound::Exact, 0, Move::NONE);
+        tt.store(keys[1], 2, Bound::Exact, 0, Move::NONE);
+        //the shallow entry makes way, the deep one stays
+        tt.store(keys[2], 1, Bound::Exact, 0, Move::NONE);
+        assert!(tt.probe(keys[0]).is_some());
+        assert!(tt.probe(keys[1]).is_none());
+        assert!(tt.probe(keys[2]).is_some());
+        //in a later search even the deep entry can be replaced
+        tt.new_search();
+        tt.store(keys[1], 1, Bound::Exact, 0, Move::NONE);
+        assert!(tt.probe(keys[0]).is_none());
+        assert_eq!(tt.hashfull(), 1);
+    }
+
+    #[test]
+    fn mate_scores() {
+        //mate in 3 plies from the root, stored at ply 2, is mate in 1 ply from there
+        let score = MATE - 3;
+        assert_eq!(score_to_tt(score, 2), MATE - 1);
+        assert_eq!(score_from_tt(score_to_tt(score, 2), 5), MATE - 6);
+        assert_eq!(score_from_tt(score_to_tt(-score, 2), 2), -score);
+        assert_eq!(score_to_tt(150, 7), 150);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::chess::{Chess, Piece};
+
+pub struct ZobristKeys {
+    pieces: [[u64; 64]; 12],
+    black_to_move: u64,
+    castling: [u64; 4], //white, white long, black, black long
+    en_passant: [u64; 8],
+}
+
+//generated at compile time with splitmix64 so keys are the same in every build
+pub static KEYS: ZobristKeys = ZobristKeys::new(0x9E37_79B9_7F4A_7C15);
+
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl ZobristKeys {
+    const fn new(seed: u64) -> Self {
+        let mut state = seed;
+        let mut pieces = [[0; 64]; 12];
+        let mut piece = 0;
+        while piece < 12 {
+            let mut square = 0;
+            while square < 64 {
+                pieces[piece][square] = splitmix64(&mut state);
+                square += 1;
+            }
+            piece += 1;
+        }
+        let black_to_move = splitmix64(&mut state);
+        let mut castling = [0; 4];
+        let mut i = 0;
+        while i < 4 {
+            castling[i] = splitmix64(&mut state);
+            i += 1;
+        }
+        let mut en_passant = [0; 8];
+        i = 0;
+        while i < 8 {
+            en_passant[i] = splitmix64(&mut state);
+            i += 1;
+        }
+        ZobristKeys {
+            pieces,
+            black_to_move,
+            castling,
+            en_passant,
+        }
+    }
+    pub fn piece(&self, piece: Piece, square: u8) -> u64 {
+        if piece == Piece::Empty {
+            return 0;
+        }
+        self.pieces[piece.index()][square as usize]
+    }
+    pub fn black_to_move(&self) -> u64 {
+        self.black_to_move
+    }
+    pub fn castling(&self, castling: [bool; 4]) -> u64 {
+        castling
+            .iter()
+            .zip(self.castling)
+            .filter(|(&right, _)| right)
+            .fold(0, |key, (_, right_key)| key ^ right_key)
+    }
+    //only the file of the en passant square matters, 64 means there is none
+    pub fn en_passant(&self, square: u8) -> u64 {
+        if square < 64 {
+            self.en_passant[square as usize % 8]
+        } else {
+            0
+        }
+    }
+}
+
+impl Chess {
+    //full recompute, move_piece keeps self.hash up to date incrementally
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (square, &piece) in self.board.iter().enumerate() {
+            hash ^= KEYS.piece(piece, square as u8);
+        }
+        if !self.is_white_turn {
+            hash ^= KEYS.black_to_move();
+        }
+        hash ^= KEYS.castling(self.castling);
+        hash ^= KEYS.en_passant(self.en_passant);
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chess::Chess;
+    use crate::fen::START_FEN;
+    use crate::perft::{walk, WALK_POSITIONS};
+
+    #[test]
+    fn transpositions_share_a_key() {
+        let mut chess = Chess::new();
+        let start = chess.hash;
+        // 1. Nf3 Nf6 2. Ng1 Ng8
+        for (from, to) in [(62, 45), (6, 21), (45, 62), (21, 6)] {
+            chess.move_piece(from, to, None);
+        }
+        assert_eq!(chess.hash, start);
+        assert_eq!(start, Chess::from_fen(START_FEN).unwrap().hash);
+    }
+
+    #[test]
+    fn side_castling_and_en_passant_change_the_key() {
+        let base = Chess::from_fen("r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        for fen in [
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R b KQkq - 0 1",
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R w Kkq - 0 1",
+            "r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1",
+        ] {
+            assert_ne!(Chess::from_fen(fen).unwrap().hash, base.hash);
+        }
+    }
+
+    #[test]
+    fn incremental_key_matches_recompute() {
+        for fen in WALK_POSITIONS {
+            let mut chess = Chess::from_fen(fen).unwrap();
+            let start = chess.hash;
+            //a wrong undo shows up in the next move's key
+            walk(&mut chess, 3, &mut |chess| {
+                assert_eq!(chess.hash, chess.compute_hash(), "{}", chess.to_fen());
+            });
+            assert_eq!(chess.hash, start);
+        }
+    }
+}